@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process::exit;
+use std::env;
+use failure::{Fallible, format_err};
+use uuid::Uuid;
+use localmc::{read_properties, find_root, server_running, rcon_settings, take_root_arg};
+use localmc::lists::{self, ListEntry, Op, WhitelistEntry, BannedPlayer, BannedIp};
+use localmc::profiles::resolve_uuid;
+
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Ops,
+    Whitelist,
+    Bans,
+    IpBans,
+}
+
+enum Action {
+    List,
+    Add(String),
+    Remove(String),
+}
+
+struct Opts {
+    kind: Kind,
+    action: Action,
+    uuid: Option<Uuid>,
+    reason: Option<String>,
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let (root, opts) = match (root, parse_args(&args[1..])) {
+        (Ok(r), Some(o)) => (r, o),
+        _ => {
+            eprintln!("Usage: {} [--root <path>] <ops|whitelist|bans|ip-bans> list", args[0]);
+            eprintln!("       {} [--root <path>] <ops|whitelist|bans|ip-bans> add <name|ip> [--uuid <uuid>] [--reason <reason>]", args[0]);
+            eprintln!("       {} [--root <path>] <ops|whitelist|bans|ip-bans> remove <name|ip>", args[0]);
+            exit(1);
+        }
+    };
+
+    let root = match find_root(root.as_deref()) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(10);
+        }
+    };
+    let props = match read_properties(&root.join("server.properties")) {
+        Ok(p) => p,
+        Err(err) => {
+            eprintln!("Error reading server.properties: {}", err);
+            exit(20);
+        }
+    };
+
+    // Listing always reads the file; the server saves every change it makes.
+    let result = match opts.action {
+        Action::List => list(&root, opts.kind),
+        _ => match server_running(&props) {
+            Ok(true) => online(&props, &opts),
+            Ok(false) => offline(&root, &props, &opts),
+            Err(e) => Err(e),
+        }
+    };
+    match result {
+        Ok(msg) => print!("{}", msg),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(30);
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Opts> {
+    let mut args = args.iter();
+    let kind = match args.next()?.as_str() {
+        "ops" => Kind::Ops,
+        "whitelist" => Kind::Whitelist,
+        "bans" => Kind::Bans,
+        "ip-bans" => Kind::IpBans,
+        _ => return None,
+    };
+    let action = args.next()?;
+    let mut target = None;
+    let mut uuid = None;
+    let mut reason = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--uuid" => uuid = Some(args.next()?.parse().ok()?),
+            "--reason" => reason = Some(args.next()?.clone()),
+            _ if target.is_none() => target = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+    let action = match (action.as_str(), target) {
+        ("list", None) => Action::List,
+        ("add", Some(t)) => Action::Add(t),
+        ("remove", Some(t)) => Action::Remove(t),
+        _ => return None,
+    };
+    Some(Opts { kind, action, uuid, reason })
+}
+
+fn list(root: &Path, kind: Kind) -> Fallible<String> {
+    Ok(match kind {
+        Kind::Ops => lists::read_list::<Op>(root)?.iter()
+            .map(|e| format!("{}\t{}\tlevel {}\n", e.name, e.uuid, e.level))
+            .collect(),
+        Kind::Whitelist => lists::read_list::<WhitelistEntry>(root)?.iter()
+            .map(|e| format!("{}\t{}\n", e.name, e.uuid))
+            .collect(),
+        Kind::Bans => lists::read_list::<BannedPlayer>(root)?.iter()
+            .map(|e| format!("{}\t{}\t{}\n", e.name, e.uuid, e.reason))
+            .collect(),
+        Kind::IpBans => lists::read_list::<BannedIp>(root)?.iter()
+            .map(|e| format!("{}\t{}\n", e.ip, e.reason))
+            .collect(),
+    })
+}
+
+/// Makes the change through rcon, so the running server records it itself.
+fn online(props: &HashMap<String, String>, opts: &Opts) -> Fallible<String> {
+    // The server's commands only take a name, which it looks up itself
+    if opts.uuid.is_some() {
+        failure::bail!("The server is running, so it looks players up itself; --uuid only works while it's stopped");
+    }
+    let (port, auth) = rcon_settings(props)?.ok_or_else(|| format_err!(
+        "The server is running but rcon is not enabled, refusing to edit {} underneath it",
+        file_name(opts.kind)
+    ))?;
+    let cmd = match (opts.kind, &opts.action) {
+        (_, Action::List) => unreachable!(),
+        (Kind::Ops, Action::Add(name)) => format!("op {}", name),
+        (Kind::Ops, Action::Remove(name)) => format!("deop {}", name),
+        (Kind::Whitelist, Action::Add(name)) => format!("whitelist add {}", name),
+        (Kind::Whitelist, Action::Remove(name)) => format!("whitelist remove {}", name),
+        (Kind::Bans, Action::Add(name)) => with_reason(format!("ban {}", name), &opts.reason),
+        (Kind::Bans, Action::Remove(name)) => format!("pardon {}", name),
+        (Kind::IpBans, Action::Add(ip)) => with_reason(format!("ban-ip {}", ip), &opts.reason),
+        (Kind::IpBans, Action::Remove(ip)) => format!("pardon-ip {}", ip),
+    };
+    let mut conn = rcon::Connection::connect(format!("localhost:{}", port), &auth)?;
+    Ok(conn.cmd(&cmd)?)
+}
+
+fn with_reason(cmd: String, reason: &Option<String>) -> String {
+    match reason {
+        Some(r) => format!("{} {}", cmd, r),
+        None => cmd,
+    }
+}
+
+/// Edits the file directly, recording entries the same way the server would.
+fn offline(root: &Path, props: &HashMap<String, String>, opts: &Opts) -> Fallible<String> {
+    let reason = opts.reason.as_deref();
+    match (opts.kind, &opts.action) {
+        (_, Action::List) => unreachable!(),
+        (Kind::Ops, Action::Add(name)) => {
+            let uuid = player_uuid(root, props, opts, name)?;
+            let level = props.get("op-permission-level").map(|l| l.parse()).unwrap_or(Ok(4))?;
+            edit(root, |ops| {
+                lists::add_entry(ops, Op { uuid, name: name.clone(), level, bypasses_player_limit: false });
+                Ok(format!("Made {} a server operator\n", name))
+            })
+        },
+        (Kind::Ops, Action::Remove(name)) =>
+            remove::<Op>(root, name, format!("Made {} no longer a server operator\n", name)),
+        (Kind::Whitelist, Action::Add(name)) => {
+            let uuid = player_uuid(root, props, opts, name)?;
+            edit(root, |wl| {
+                lists::add_entry(wl, WhitelistEntry { uuid, name: name.clone() });
+                Ok(format!("Added {} to the whitelist\n", name))
+            })
+        },
+        (Kind::Whitelist, Action::Remove(name)) =>
+            remove::<WhitelistEntry>(root, name, format!("Removed {} from the whitelist\n", name)),
+        (Kind::Bans, Action::Add(name)) => {
+            let uuid = player_uuid(root, props, opts, name)?;
+            edit(root, |bans| {
+                let ban = BannedPlayer::new(uuid, name, reason);
+                let msg = format!("Banned {}: {}\n", name, ban.reason);
+                lists::add_entry(bans, ban);
+                Ok(msg)
+            })
+        },
+        (Kind::Bans, Action::Remove(name)) =>
+            remove::<BannedPlayer>(root, name, format!("Unbanned {}\n", name)),
+        (Kind::IpBans, Action::Add(ip)) => {
+            ip.parse::<IpAddr>().map_err(|_| format_err!("{} is not an IP address", ip))?;
+            edit(root, |bans| {
+                let ban = BannedIp::new(ip, reason);
+                let msg = format!("Banned IP {}: {}\n", ip, ban.reason);
+                lists::add_entry(bans, ban);
+                Ok(msg)
+            })
+        },
+        (Kind::IpBans, Action::Remove(ip)) =>
+            remove::<BannedIp>(root, ip, format!("Unbanned IP {}\n", ip)),
+    }
+}
+
+/// The UUID given on the command line, or else the one the server would use.
+fn player_uuid(root: &Path, props: &HashMap<String, String>, opts: &Opts, name: &str) -> Fallible<Uuid> {
+    match opts.uuid {
+        Some(uuid) => Ok(uuid),
+        None => resolve_uuid(root, props, name),
+    }
+}
+
+fn edit<E: ListEntry>(root: &Path, f: impl FnOnce(&mut Vec<E>) -> Fallible<String>) -> Fallible<String> {
+    let mut entries = lists::read_list::<E>(root)?;
+    let msg = f(&mut entries)?;
+    lists::write_list(root, &entries)?;
+    Ok(msg)
+}
+
+fn remove<E: ListEntry>(root: &Path, key: &str, msg: String) -> Fallible<String> {
+    edit::<E>(root, |entries| {
+        if lists::remove_entry(entries, key) {
+            Ok(msg)
+        } else {
+            Err(format_err!("Nothing changed, {} is not in {}", key, E::FILE))
+        }
+    })
+}
+
+fn file_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Ops => Op::FILE,
+        Kind::Whitelist => WhitelistEntry::FILE,
+        Kind::Bans => BannedPlayer::FILE,
+        Kind::IpBans => BannedIp::FILE,
+    }
+}
@@ -1,23 +1,29 @@
 use std::path::Path;
 use std::process::exit;
 use std::env;
+use std::io::IsTerminal;
 use failure::Error;
-use localmc::{read_properties, find_serverprops};
+use localmc::{read_properties, find_serverprops, take_root_arg};
 use rcon;
+use mcproto_min_async::text::TextComponent;
 
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <cmd>", args[0]);
-        exit(1);
-    }
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let root = match root {
+        Ok(r) if args.len() == 2 => r,
+        _ => {
+            eprintln!("Usage: {} [--root <path>] <cmd>", args[0]);
+            exit(1);
+        }
+    };
     let cmd = &args[1];
 
-    match read_portauth(&match find_serverprops() {
-        Some(p) => p,
-        None => {
-            eprintln!("Unable to find server.properties");
+    match read_portauth(&match find_serverprops(root.as_deref()) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
             exit(10);
         }
     }) {
@@ -25,7 +31,13 @@ fn main() {
             let address = format!("localhost:{}", port);
             match run_cmd(&address, &auth, &cmd) {
                 Ok(msg) => {
-                    print!("{}", msg);
+                    // Plugins color their output with legacy codes
+                    let msg = TextComponent::from_legacy(&msg);
+                    if std::io::stdout().is_terminal() {
+                        print!("{}", msg.to_ansi());
+                    } else {
+                        print!("{}", msg.to_plain());
+                    }
                 },
                 Err(e) => {
                     eprintln!("Error running command: {}", e);
@@ -0,0 +1,360 @@
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::env;
+use failure::{Fallible, format_err};
+use signal_hook::consts::signal::*;
+use signal_hook::iterator::Signals;
+use mcproto_min_async as mcp;
+use mcp::client;
+use localmc::icon::check_server_icon;
+use localmc::{find_root, read_properties, server_port, rcon_settings, take_root_arg, PAUSED_MARKER};
+
+
+#[derive(Clone, Copy, PartialEq)]
+enum IdleAction {
+    Stop,
+    Pause,
+}
+
+struct Opts {
+    root: Option<PathBuf>,
+    /// Minutes without players before doing something about it
+    idle: Option<u32>,
+    idle_action: IdleAction,
+    sleeper: String,
+    command: Vec<String>,
+}
+
+fn parse_idle_action(s: &str) -> Option<IdleAction> {
+    match s {
+        "stop" => Some(IdleAction::Stop),
+        "pause" => Some(IdleAction::Pause),
+        _ => None,
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Opts> {
+    let mut opts = Opts {
+        root: None,
+        idle: match env::var("MINECRAFT_IDLE_MINUTES") {
+            Ok(m) => Some(m.parse().ok()?),
+            Err(_) => None,
+        },
+        idle_action: match env::var("MINECRAFT_IDLE_ACTION") {
+            Ok(a) => parse_idle_action(&a)?,
+            Err(_) => IdleAction::Stop,
+        },
+        sleeper: "sleeper".to_string(),
+        command: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--idle" => opts.idle = Some(args.next()?.parse().ok()?),
+            "--idle-action" => opts.idle_action = parse_idle_action(args.next()?)?,
+            "--sleeper" => opts.sleeper = args.next()?.clone(),
+            "--" => {
+                opts.command.extend(args.cloned());
+                break;
+            }
+            _ => {
+                // Everything from the command on is the command's
+                opts.command.push(arg.clone());
+                opts.command.extend(args.cloned());
+                break;
+            }
+        }
+    }
+    Some(opts)
+}
+
+fn log(msg: &str) {
+    eprintln!("entrypoint: {}", msg);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let mut opts = match (root, parse_args(&args[1..])) {
+        (Ok(r), Some(mut o)) => {
+            o.root = r;
+            o
+        }
+        _ => {
+            eprintln!("Usage: {} [--root <path>] [--idle <minutes>] [--idle-action stop|pause] [--sleeper <path>] [<command> [<args>...]]", args[0]);
+            exit(1);
+        }
+    };
+
+    // The server is optional, unless something needs it
+    let root = find_root(opts.root.as_deref());
+    let root = match root {
+        Ok(r) => Some(r),
+        Err(e) if opts.idle.is_some() || opts.command.is_empty() => {
+            eprintln!("{}", e);
+            exit(10);
+        }
+        Err(_) => None,
+    };
+    if let Some(ref root) = root {
+        // Left over from a container that was killed while paused
+        let _ = fs::remove_file(root.join(PAUSED_MARKER));
+        // The server doesn't say much when it skips the icon
+        if let Err(e) = check_server_icon(root) {
+            log(&format!("warning: the server will ignore its icon: {}", e));
+        }
+    }
+    if opts.command.is_empty() {
+        opts.command.push(root.as_ref().unwrap().join("launch").to_string_lossy().into_owned());
+    }
+
+    let mut supervisor = Supervisor { opts, root, child: 0, state: State::Running { idle: 0 }, ticks: 0 };
+    if let Err(e) = supervisor.run() {
+        log(&e.to_string());
+        exit(30);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// The server is up, and has had no players for this many minutes
+    Running { idle: u32 },
+    /// Stopping the server because nobody's playing
+    Stopping,
+    /// `sleeper` is holding the port until someone wants to play
+    Sleeping,
+    /// The server is stopped with SIGSTOP until someone connects
+    Paused,
+    /// The container is stopping
+    ShuttingDown,
+}
+
+enum Event {
+    Signal(i32),
+    Tick,
+}
+
+struct Supervisor {
+    opts: Opts,
+    root: Option<PathBuf>,
+    /// Also the process group, since children get their own
+    child: i32,
+    state: State,
+    /// Seconds into the current minute
+    ticks: u32,
+}
+
+impl Supervisor {
+    fn spawn(&mut self, command: &[String]) -> Fallible<()> {
+        let child = Command::new(&command[0])
+            .args(&command[1..])
+            .process_group(0)
+            .spawn()
+            .map_err(|e| format_err!("Unable to run {}: {}", command[0], e))?;
+        // Waited for by reap(), along with any orphans
+        self.child = child.id() as i32;
+        Ok(())
+    }
+
+    fn spawn_server(&mut self) -> Fallible<()> {
+        let command = self.opts.command.clone();
+        self.spawn(&command)?;
+        self.state = State::Running { idle: 0 };
+        Ok(())
+    }
+
+    fn signal_group(&self, sig: i32) {
+        unsafe { libc::kill(-self.child, sig) };
+    }
+
+    fn run(&mut self) -> Fallible<()> {
+        let (tx, events) = mpsc::channel();
+        let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP, SIGUSR1, SIGUSR2, SIGCHLD])?;
+        let signal_tx = tx.clone();
+        thread::spawn(move || {
+            for sig in signals.forever() {
+                if signal_tx.send(Event::Signal(sig)).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            if tx.send(Event::Tick).is_err() {
+                break;
+            }
+        });
+
+        self.spawn_server()?;
+        for event in events {
+            match event {
+                Event::Signal(SIGCHLD) => {
+                    if let Some(code) = self.reap()? {
+                        exit(code);
+                    }
+                }
+                Event::Signal(SIGTERM) | Event::Signal(SIGINT) => self.shut_down(),
+                Event::Signal(sig) => self.signal_group(sig),
+                Event::Tick => self.tick(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for whatever has exited. Gives an exit code if it's time to go.
+    fn reap(&mut self) -> Fallible<Option<i32>> {
+        loop {
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+            if pid <= 0 {
+                return Ok(None);
+            }
+            if pid != self.child {
+                // An orphan
+                continue;
+            }
+            let code = if libc::WIFEXITED(status) {
+                libc::WEXITSTATUS(status)
+            } else {
+                128 + libc::WTERMSIG(status)
+            };
+            match self.state {
+                State::Stopping => {
+                    log("Server stopped, sleeping until someone wants to play");
+                    let mut command = vec![self.opts.sleeper.clone(), "--exit".to_string()];
+                    if let Some(ref root) = self.root {
+                        command.extend(vec!["--root".to_string(), root.to_string_lossy().into_owned()]);
+                    }
+                    self.spawn(&command)?;
+                    self.state = State::Sleeping;
+                }
+                State::Sleeping if code == 0 => {
+                    log("Someone wants to play, starting the server");
+                    self.spawn_server()?;
+                }
+                _ => return Ok(Some(code)),
+            }
+        }
+    }
+
+    /// Stops the server the way it likes: through rcon if it can, and SIGTERM if not.
+    fn stop_server(&self) {
+        if let Some(ref root) = self.root {
+            match rcon_stop(root) {
+                Ok(()) => return,
+                Err(e) => log(&format!("Unable to stop the server through rcon ({}), sending SIGTERM", e)),
+            }
+        }
+        self.signal_group(SIGTERM);
+    }
+
+    fn shut_down(&mut self) {
+        match self.state {
+            State::Running { .. } => self.stop_server(),
+            State::Paused => {
+                self.set_paused(false);
+                self.stop_server();
+            }
+            State::Sleeping => self.signal_group(SIGTERM),
+            // Already on the way out
+            State::Stopping | State::ShuttingDown => (),
+        }
+        self.state = State::ShuttingDown;
+    }
+
+    /// Freezes or thaws the server, and leaves a marker in the root while it's frozen so the
+    /// healthcheck leaves it be.
+    fn set_paused(&mut self, paused: bool) {
+        if let Some(ref root) = self.root {
+            let marker = root.join(PAUSED_MARKER);
+            let result = if paused { fs::write(&marker, b"") } else { fs::remove_file(&marker) };
+            if let Err(e) = result {
+                log(&format!("Unable to update {}: {}", marker.display(), e));
+            }
+        }
+        if paused {
+            self.signal_group(SIGSTOP);
+            self.state = State::Paused;
+        } else {
+            self.signal_group(SIGCONT);
+            self.state = State::Running { idle: 0 };
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ticks = (self.ticks + 1) % 60;
+        let (root, limit) = match (&self.root, self.opts.idle) {
+            (Some(root), Some(limit)) => (root.clone(), limit),
+            _ => return,
+        };
+        match self.state {
+            State::Running { idle } if self.ticks == 0 => {
+                let idle = match players_online(&root) {
+                    Ok(0) => idle + 1,
+                    // Still starting up, or someone's on
+                    _ => 0,
+                };
+                self.state = State::Running { idle };
+                if idle >= limit {
+                    log(&format!("Nobody has played for {} minutes", idle));
+                    match self.opts.idle_action {
+                        IdleAction::Stop => {
+                            self.state = State::Stopping;
+                            self.stop_server();
+                        }
+                        IdleAction::Pause => self.set_paused(true),
+                    }
+                }
+            }
+            State::Paused => {
+                if let Ok(true) = read_properties(&root.join("server.properties")).and_then(|p| server_port(&p)).map(connection_waiting) {
+                    log("Someone is connecting, resuming the server");
+                    self.set_paused(false);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn rcon_stop(root: &Path) -> Fallible<()> {
+    let props = read_properties(&root.join("server.properties"))?;
+    let (port, auth) = rcon_settings(&props)?.ok_or_else(|| format_err!("rcon is not enabled"))?;
+    let mut conn = rcon::Connection::connect(format!("localhost:{}", port), &auth)?;
+    conn.cmd("stop")?;
+    Ok(())
+}
+
+/// Asks the server how many players are on, with a status ping.
+fn players_online(root: &Path) -> Fallible<i64> {
+    let port = server_port(&read_properties(&root.join("server.properties"))?)?;
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let settings = client::Settings::new().set_host("localhost", port).set_timeout(Duration::from_secs(5));
+    let status = rt.block_on(async {
+        client::connect_with_settings(&format!("127.0.0.1:{}", port), &settings).await?.status().await
+    })?;
+    status.players.map(|p| p.online).ok_or_else(|| format_err!("The status doesn't say how many players are on"))
+}
+
+/// Whether anyone is waiting to be accepted on the port, which a stopped server can't do.
+fn connection_waiting(port: u16) -> bool {
+    let listening = format!(":{:04X}", port);
+    ["/proc/net/tcp", "/proc/net/tcp6"].iter().any(|table| {
+        let table = match fs::read_to_string(table) {
+            Ok(t) => t,
+            Err(_) => return false,
+        };
+        // sl local_address rem_address st tx_queue:rx_queue ...
+        table.lines().skip(1).any(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // For listening sockets, the receive queue is connections waiting to be accepted
+            fields.len() > 4 && fields[1].ends_with(&listening) && fields[3] == "0A"
+                && fields[4].split(':').nth(1).is_some_and(|rx| !rx.trim_start_matches('0').is_empty())
+        })
+    })
+}
@@ -0,0 +1,66 @@
+//! The server's icon, `server-icon.png`, which it sends along with status responses.
+//!
+//! The server only uses a 64x64 PNG. Anything else gets a stack trace in its log at startup and
+//! no icon, which is easy to miss.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder};
+use failure::Fallible;
+
+pub const ICON_FILE: &str = "server-icon.png";
+/// How wide and high the icon has to be.
+pub const ICON_SIZE: u32 = 64;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Fail)]
+pub enum IconError {
+    #[fail(display = "server-icon.png is not a PNG")]
+    NotPng,
+    #[fail(display = "server-icon.png is {}x{}, but it needs to be 64x64", _0, _1)]
+    WrongSize(u32, u32),
+}
+
+/// The width and height of a PNG, from its header.
+pub fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    // The signature, then IHDR: its length, its type, the width and the height
+    if data.len() < 24 || !data.starts_with(PNG_SIGNATURE) || &data[12..16] != b"IHDR" {
+        return None;
+    }
+    Some((BigEndian::read_u32(&data[16..20]), BigEndian::read_u32(&data[20..24])))
+}
+
+/// Checks that an image is one the server will use as its icon.
+///
+/// ```
+/// use localmc::icon::{check_icon, IconError};
+/// let header = |w: u8, h: u8| [
+///     &b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"[..],
+///     &[0, 0, 0, w, 0, 0, 0, h, 8, 6, 0, 0, 0],
+/// ].concat();
+/// assert!(check_icon(&header(64, 64)).is_ok());
+/// assert!(matches!(check_icon(&header(128, 128)), Err(IconError::WrongSize(128, 128))));
+/// assert!(matches!(check_icon(b"GIF89a"), Err(IconError::NotPng)));
+/// ```
+pub fn check_icon(data: &[u8]) -> Result<(), IconError> {
+    match png_size(data) {
+        None => Err(IconError::NotPng),
+        Some((ICON_SIZE, ICON_SIZE)) => Ok(()),
+        Some((w, h)) => Err(IconError::WrongSize(w, h)),
+    }
+}
+
+/// Checks the icon in the minecraft root. `Ok(false)` if there isn't one.
+pub fn check_server_icon(root: &Path) -> Fallible<bool> {
+    match fs::read(root.join(ICON_FILE)) {
+        Ok(data) => {
+            check_icon(&data)?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
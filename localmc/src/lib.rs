@@ -13,11 +13,21 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use std::env;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::time::Duration;
 
 #[macro_use] extern crate failure;
 
 use failure::Fallible;
 
+pub mod icon;
+pub mod lists;
+pub mod profiles;
+pub mod player;
+pub mod region;
+pub mod world;
+
 enum PropLine {
     Comment,
     Prop(String, String)
@@ -56,22 +66,122 @@ pub fn read_properties(path: &Path) -> Fallible<HashMap<String, String>>  {
 }
 
 
-pub fn find_root() -> Option<PathBuf> {
-    // 1. Environment variable MINECRAFT_ROOT
-    for (key, value) in env::vars() {
-        if key == "MINECRAFT_ROOT" {
-            return Some(PathBuf::from(value));
+/// Where the root is looked for when nothing else points at it. Set `MINECRAFT_DEFAULT_ROOT` at
+/// build time to change it.
+pub const DEFAULT_ROOT: &str = match option_env!("MINECRAFT_DEFAULT_ROOT") {
+    Some(p) => p,
+    None => "/mc",
+};
+
+#[derive(Debug)]
+pub struct RootNotFound {
+    /// Each strategy that was attempted, and the directory it looked in
+    pub tried: Vec<(&'static str, PathBuf)>
+}
+
+impl fmt::Display for RootNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to find server.properties, tried:")?;
+        for (how, path) in &self.tried {
+            write!(f, "\n  {} ({})", path.display(), how)?;
         }
+        Ok(())
+    }
+}
+
+impl failure::Fail for RootNotFound {}
+
+fn has_serverprops(dir: &Path) -> bool {
+    dir.join("server.properties").is_file()
+}
+
+/// Finds the minecraft root, the directory holding server.properties.
+///
+/// In order, this tries:
+/// 1. The given override (from `--root`)
+/// 2. The environment variable `MINECRAFT_ROOT`
+/// 3. The current directory and its ancestors
+/// 4. [`DEFAULT_ROOT`]
+///
+/// An explicit root (1 or 2) that doesn't hold server.properties is an error, rather than falling
+/// through to a different server.
+pub fn find_root(root_override: Option<&Path>) -> Result<PathBuf, RootNotFound> {
+    let mut tried = Vec::new();
+    let explicit = root_override.map(|p| ("--root", p.to_path_buf()))
+        .or_else(|| env::var_os("MINECRAFT_ROOT").map(|p| ("MINECRAFT_ROOT", PathBuf::from(p))));
+    if let Some((how, path)) = explicit {
+        if has_serverprops(&path) {
+            return Ok(path);
+        }
+        tried.push((how, path));
+        return Err(RootNotFound { tried });
+    }
+
+    if let Ok(cwd) = env::current_dir() {
+        for dir in cwd.ancestors() {
+            if has_serverprops(dir) {
+                return Ok(dir.to_path_buf());
+            }
+        }
+        tried.push(("current directory and its parents", cwd));
+    }
+
+    let default_root = Path::new(DEFAULT_ROOT);
+    if has_serverprops(default_root) {
+        return Ok(default_root.to_path_buf());
+    }
+    tried.push(("default", default_root.to_path_buf()));
+
+    Err(RootNotFound { tried })
+}
+
+pub fn find_serverprops(root_override: Option<&Path>) -> Result<PathBuf, RootNotFound> {
+    find_root(root_override).map(|p| p.join("server.properties"))
+}
+
+/// Pulls `--root <path>` (or `--root=<path>`) out of the command line arguments.
+pub fn take_root_arg(args: &mut Vec<String>) -> Fallible<Option<PathBuf>> {
+    let pos = match args.iter().position(|a| a == "--root" || a.starts_with("--root=")) {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+    let arg = args.remove(pos);
+    if let Some(path) = arg.strip_prefix("--root=") {
+        return Ok(Some(PathBuf::from(path)));
     }
-    // 2. The container's predefined root
-    let container_root = Path::new("/mc"); // TODO: Can we read this from a compile flag?
-    if container_root.exists() {
-        return Some(container_root.to_path_buf());
+    if pos >= args.len() {
+        bail!("--root needs a path");
     }
-    // Can't find it
-    None
+    Ok(Some(PathBuf::from(args.remove(pos))))
 }
 
-pub fn find_serverprops() -> Option<PathBuf> {
-    find_root().map(|p| p.join("server.properties"))
+/// The port the server listens on, according to server.properties.
+pub fn server_port(props: &HashMap<String, String>) -> Fallible<u16> {
+    Ok(props.get("server-port").map(|p| p.parse()).unwrap_or(Ok(25565))?)
+}
+
+/// Checks if the server is up by connecting to its port on localhost.
+pub fn server_running(props: &HashMap<String, String>) -> Fallible<bool> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, server_port(props)?));
+    Ok(TcpStream::connect_timeout(&addr, Duration::from_secs(1)).is_ok())
+}
+
+/// Left in the minecraft root by `entrypoint` while it has the server paused, since connecting to
+/// check on it would wake it up.
+pub const PAUSED_MARKER: &str = ".paused";
+
+/// Whether `entrypoint` has the server paused.
+pub fn server_paused(root: &Path) -> bool {
+    root.join(PAUSED_MARKER).exists()
+}
+
+/// The rcon port and password, if rcon is enabled.
+pub fn rcon_settings(props: &HashMap<String, String>) -> Fallible<Option<(u16, String)>> {
+    if props.get("enable-rcon").map(String::as_str) != Some("true") {
+        return Ok(None);
+    }
+    Ok(Some((
+        props.get("rcon.port").map(|p| p.parse()).unwrap_or(Ok(25575))?,
+        props.get("rcon.password").cloned().unwrap_or_default()
+    )))
 }
@@ -0,0 +1,180 @@
+//! The player lists the server keeps in its root: `ops.json`, `whitelist.json`,
+//! `banned-players.json` and `banned-ips.json`.
+//!
+//! These should only be written while the server is stopped; a running server keeps its own copy
+//! in memory and will overwrite the file the next time it changes.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use failure::Fallible;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+/// The `source` the server records for bans made from the console.
+pub const DEFAULT_SOURCE: &str = "Server";
+/// The reason the server records when a ban doesn't give one.
+pub const DEFAULT_REASON: &str = "Banned by an operator.";
+/// The `expires` value for permanent bans.
+pub const FOREVER: &str = "forever";
+
+/// An entry in one of the server's JSON lists.
+pub trait ListEntry: Serialize + DeserializeOwned {
+    /// The name of the file in the minecraft root
+    const FILE: &'static str;
+    /// The player name or address the entry is looked up by
+    fn key(&self) -> &str;
+    /// The player the entry is for, if it's for a player
+    fn uuid(&self) -> Option<Uuid> { None }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Op {
+    pub uuid: Uuid,
+    pub name: String,
+    pub level: u8,
+    pub bypasses_player_limit: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhitelistEntry {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BannedPlayer {
+    pub uuid: Uuid,
+    pub name: String,
+    pub created: String,
+    pub source: String,
+    pub expires: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BannedIp {
+    pub ip: String,
+    pub created: String,
+    pub source: String,
+    pub expires: String,
+    pub reason: String,
+}
+
+impl ListEntry for Op {
+    const FILE: &'static str = "ops.json";
+    fn key(&self) -> &str { &self.name }
+    fn uuid(&self) -> Option<Uuid> { Some(self.uuid) }
+}
+
+impl ListEntry for WhitelistEntry {
+    const FILE: &'static str = "whitelist.json";
+    fn key(&self) -> &str { &self.name }
+    fn uuid(&self) -> Option<Uuid> { Some(self.uuid) }
+}
+
+impl ListEntry for BannedPlayer {
+    const FILE: &'static str = "banned-players.json";
+    fn key(&self) -> &str { &self.name }
+    fn uuid(&self) -> Option<Uuid> { Some(self.uuid) }
+}
+
+impl ListEntry for BannedIp {
+    const FILE: &'static str = "banned-ips.json";
+    fn key(&self) -> &str { &self.ip }
+}
+
+impl BannedPlayer {
+    /// A permanent ban, as the server would record it from the console.
+    pub fn new(uuid: Uuid, name: &str, reason: Option<&str>) -> Self {
+        BannedPlayer {
+            uuid,
+            name: name.to_string(),
+            created: timestamp(),
+            source: DEFAULT_SOURCE.to_string(),
+            expires: FOREVER.to_string(),
+            reason: reason.unwrap_or(DEFAULT_REASON).to_string(),
+        }
+    }
+}
+
+impl BannedIp {
+    /// A permanent ban, as the server would record it from the console.
+    pub fn new(ip: &str, reason: Option<&str>) -> Self {
+        BannedIp {
+            ip: ip.to_string(),
+            created: timestamp(),
+            source: DEFAULT_SOURCE.to_string(),
+            expires: FOREVER.to_string(),
+            reason: reason.unwrap_or(DEFAULT_REASON).to_string(),
+        }
+    }
+}
+
+/// The current time, in the format the server uses for ban dates.
+pub fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S %z").to_string()
+}
+
+/// Reads a list from the minecraft root. A missing file is an empty list.
+pub fn read_list<E: ListEntry>(root: &Path) -> Fallible<Vec<E>> {
+    match File::open(root.join(E::FILE)) {
+        Ok(f) => Ok(serde_json::from_reader(BufReader::new(f))?),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replaces a list in the minecraft root.
+///
+/// The file is written next to the original and renamed over it, so a failed write doesn't lose
+/// the list.
+pub fn write_list<E: ListEntry>(root: &Path, entries: &[E]) -> Fallible<()> {
+    let path = root.join(E::FILE);
+    let tmp = root.join(format!(".{}.tmp", E::FILE));
+    {
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut w, entries)?;
+        w.flush()?;
+    }
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+/// Finds an entry by name or address. Like the server, names are matched case-insensitively.
+pub fn find_entry<'a, E: ListEntry>(entries: &'a [E], key: &str) -> Option<&'a E> {
+    entries.iter().find(|e| e.key().eq_ignore_ascii_case(key))
+}
+
+/// Adds an entry, replacing any existing entry with the same key or, for players, the same UUID.
+/// A player who has changed their name keeps a single entry.
+///
+/// ```
+/// use uuid::Uuid;
+/// use localmc::lists::{self, WhitelistEntry};
+/// let (a, b) = (Uuid::from_u128(1), Uuid::from_u128(2));
+/// let mut wl = vec![
+///     WhitelistEntry { uuid: a, name: "Alice".to_string() },
+///     WhitelistEntry { uuid: b, name: "Bob".to_string() },
+/// ];
+/// lists::add_entry(&mut wl, WhitelistEntry { uuid: a, name: "Alicia".to_string() });
+/// lists::add_entry(&mut wl, WhitelistEntry { uuid: b, name: "bob".to_string() });
+/// let names: Vec<_> = wl.iter().map(|e| (e.name.as_str(), e.uuid)).collect();
+/// assert_eq!(names, [("Alicia", a), ("bob", b)]);
+/// ```
+pub fn add_entry<E: ListEntry>(entries: &mut Vec<E>, entry: E) {
+    entries.retain(|e| {
+        !e.key().eq_ignore_ascii_case(entry.key()) && (e.uuid().is_none() || e.uuid() != entry.uuid())
+    });
+    entries.push(entry);
+}
+
+/// Removes an entry by name or address, returning whether there was one.
+pub fn remove_entry<E: ListEntry>(entries: &mut Vec<E>, key: &str) -> bool {
+    let before = entries.len();
+    entries.retain(|e| !e.key().eq_ignore_ascii_case(key));
+    entries.len() != before
+}
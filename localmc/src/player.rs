@@ -0,0 +1,142 @@
+//! Reading what the world saves about each player: `playerdata/<uuid>.dat`, `stats/<uuid>.json`
+//! and `advancements/<uuid>.json`.
+//!
+//! The server only writes these periodically and when players leave, so they can be a few
+//! minutes behind for online players.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use failure::Fallible;
+use mcproto_min_async::nbt::{self, Tag};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerData {
+    #[serde(rename = "Pos")]
+    pub pos: Vec<f64>,
+    /// A number before 1.16 and a name since, see [`PlayerData::dimension_name`]
+    #[serde(rename = "Dimension")]
+    pub dimension: Tag,
+    #[serde(rename = "Health")]
+    pub health: f32,
+    #[serde(rename = "foodLevel", default)]
+    pub food_level: i32,
+    #[serde(rename = "playerGameType", default)]
+    pub game_type: i32,
+    #[serde(rename = "XpLevel", default)]
+    pub xp_level: i32,
+    #[serde(rename = "XpTotal", default)]
+    pub xp_total: i32,
+    #[serde(rename = "Inventory", default)]
+    pub inventory: Vec<Item>,
+    #[serde(rename = "EnderItems", default)]
+    pub ender_items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Item {
+    #[serde(rename = "Slot")]
+    pub slot: i8,
+    pub id: String,
+    #[serde(rename = "Count", alias = "count")]
+    pub count: i32,
+}
+
+impl PlayerData {
+    pub fn dimension_name(&self) -> String {
+        match &self.dimension {
+            Tag::String(name) => name.clone(),
+            tag => match tag.as_i64() {
+                Some(0) => "minecraft:overworld".to_string(),
+                Some(-1) => "minecraft:the_nether".to_string(),
+                Some(1) => "minecraft:the_end".to_string(),
+                Some(n) => format!("dimension {}", n),
+                None => "unknown".to_string(),
+            },
+        }
+    }
+}
+
+/// Adds up stacks of the same item.
+pub fn item_totals(items: &[Item]) -> BTreeMap<&str, i64> {
+    let mut totals = BTreeMap::new();
+    for item in items {
+        *totals.entry(item.id.as_str()).or_insert(0) += item.count as i64;
+    }
+    totals
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    /// Time spent online, in ticks
+    pub play_time: Option<i64>,
+    /// The whole file, since the statistics available vary by version
+    pub raw: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Advancements {
+    pub done: Vec<String>,
+    pub in_progress: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdvancementProgress {
+    #[serde(default)]
+    done: bool,
+}
+
+fn open_optional(path: &Path) -> Fallible<Option<File>> {
+    match File::open(path) {
+        Ok(f) => Ok(Some(f)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads a player's saved state, if they have ever joined this world.
+pub fn read_player(world: &Path, uuid: &Uuid) -> Fallible<Option<PlayerData>> {
+    match open_optional(&world.join("playerdata").join(format!("{}.dat", uuid)))? {
+        Some(f) => Ok(Some(nbt::from_reader(f)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn read_stats(world: &Path, uuid: &Uuid) -> Fallible<Option<Stats>> {
+    let f = match open_optional(&world.join("stats").join(format!("{}.json", uuid)))? {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    let raw: serde_json::Value = serde_json::from_reader(BufReader::new(f))?;
+    // 1.17 renamed play_one_minute (which always counted ticks), and before 1.13 the file was flat
+    let custom = &raw["stats"]["minecraft:custom"];
+    let play_time = custom["minecraft:play_time"].as_i64()
+        .or_else(|| custom["minecraft:play_one_minute"].as_i64())
+        .or_else(|| raw["stat.playOneMinute"].as_i64());
+    Ok(Some(Stats { play_time, raw }))
+}
+
+/// Reads a player's advancements, leaving out recipe unlocks.
+pub fn read_advancements(world: &Path, uuid: &Uuid) -> Fallible<Option<Advancements>> {
+    let f = match open_optional(&world.join("advancements").join(format!("{}.json", uuid)))? {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    let raw: BTreeMap<String, serde_json::Value> = serde_json::from_reader(BufReader::new(f))?;
+    let mut advancements = Advancements { done: Vec::new(), in_progress: Vec::new() };
+    for (name, progress) in raw {
+        if name == "DataVersion" || name.contains(":recipes/") {
+            continue;
+        }
+        if serde_json::from_value::<AdvancementProgress>(progress)?.done {
+            advancements.done.push(name);
+        } else {
+            advancements.in_progress.push(name);
+        }
+    }
+    Ok(Some(advancements))
+}
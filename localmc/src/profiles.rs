@@ -0,0 +1,64 @@
+//! Resolving player names to UUIDs without going to Mojang.
+//!
+//! Offline-mode servers derive UUIDs from the name itself. Online-mode servers get them from the
+//! session server, but remember every player that has joined in `usercache.json`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use failure::Fallible;
+use serde::{Deserialize, Serialize};
+use mcproto_min_async::protocol::login;
+use uuid::Uuid;
+
+#[derive(Debug, Fail)]
+pub enum ResolveError {
+    #[fail(display = "{} is not in usercache.json, they need to join the server once first", _0)]
+    NotCached(String)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedProfile {
+    pub name: String,
+    pub uuid: Uuid,
+    pub expires_on: String,
+}
+
+/// The UUID an offline-mode server gives a player, which is `UUID.nameUUIDFromBytes` of
+/// `OfflinePlayer:<name>`.
+///
+/// ```
+/// assert_eq!(localmc::profiles::offline_uuid("Notch").to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+/// ```
+pub fn offline_uuid(name: &str) -> Uuid {
+    Uuid::from_u128(login::offline_uuid(name))
+}
+
+/// Reads `usercache.json` from the minecraft root. A missing file is an empty cache.
+pub fn read_usercache(root: &Path) -> Fallible<Vec<CachedProfile>> {
+    match File::open(root.join("usercache.json")) {
+        Ok(f) => Ok(serde_json::from_reader(BufReader::new(f))?),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether the server authenticates players, according to server.properties.
+pub fn online_mode(props: &HashMap<String, String>) -> bool {
+    props.get("online-mode").map(String::as_str) != Some("false")
+}
+
+/// Finds the UUID the server will use for a player. Expired cache entries are still used, since
+/// a player's UUID never changes.
+pub fn resolve_uuid(root: &Path, props: &HashMap<String, String>, name: &str) -> Fallible<Uuid> {
+    if !online_mode(props) {
+        return Ok(offline_uuid(name));
+    }
+    read_usercache(root)?.iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .map(|p| p.uuid)
+        .ok_or_else(|| ResolveError::NotCached(name.to_string()).into())
+}
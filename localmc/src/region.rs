@@ -0,0 +1,524 @@
+//! Reading Anvil region files (`r.<x>.<z>.mca`), which hold the chunks of a 32x32 chunk area.
+//!
+//! A region file starts with two 4KiB tables: where each chunk is, in 4KiB sectors, and when
+//! each chunk was last saved. Each chunk is a length, a compression type, and compressed NBT.
+//! Chunks too big for the region file are stored next to it in `c.<x>.<z>.mcc`.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE, LE};
+use failure::Fallible;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use mcproto_min_async::nbt;
+use serde::{Serialize, de::DeserializeOwned};
+
+pub const SECTOR: u64 = 4096;
+/// Chunks in a region
+pub const CHUNKS: usize = 32 * 32;
+const HEADER_SECTORS: u32 = 2;
+const EXTERNAL: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Compression {
+    Gzip,
+    Zlib,
+    None,
+    Lz4,
+    /// A type this doesn't know
+    Unknown(u8),
+}
+
+impl From<u8> for Compression {
+    fn from(id: u8) -> Self {
+        match id {
+            1 => Compression::Gzip,
+            2 => Compression::Zlib,
+            3 => Compression::None,
+            4 => Compression::Lz4,
+            id => Compression::Unknown(id),
+        }
+    }
+}
+
+impl Compression {
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::Gzip => 1,
+            Compression::Zlib => 2,
+            Compression::None => 3,
+            Compression::Lz4 => 4,
+            Compression::Unknown(id) => id,
+        }
+    }
+}
+
+/// Something wrong with a chunk's entry in the region header, or the data it points at.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ChunkProblem {
+    /// The chunk claims to start inside the header
+    InHeader,
+    /// The chunk's sectors run past the end of the file
+    PastEnd,
+    /// The chunk shares sectors with the chunk at this index
+    Overlaps(usize),
+    /// The length prefix is zero or bigger than the sectors allocated for it
+    BadLength(u32),
+    UnknownCompression(u8),
+    /// The chunk is stored externally, but its `.mcc` file is gone
+    MissingExternal,
+    /// The chunk's data couldn't be read
+    Unreadable(String),
+}
+
+impl fmt::Display for ChunkProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChunkProblem::InHeader => write!(f, "starts inside the header"),
+            ChunkProblem::PastEnd => write!(f, "runs past the end of the file"),
+            ChunkProblem::Overlaps(other) => write!(f, "overlaps chunk {}", other),
+            ChunkProblem::BadLength(len) => write!(f, "has a bad length of {} bytes", len),
+            ChunkProblem::UnknownCompression(id) => write!(f, "has unknown compression type {}", id),
+            ChunkProblem::MissingExternal => write!(f, "is missing its .mcc file"),
+            ChunkProblem::Unreadable(e) => write!(f, "is unreadable: {}", e),
+        }
+    }
+}
+
+/// A chunk's entry in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Location {
+    /// In sectors from the start of the file
+    pub offset: u32,
+    pub sectors: u8,
+}
+
+impl Location {
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0 && self.sectors == 0
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkInfo {
+    /// Index in the header, which is `x + z * 32` within the region
+    pub index: usize,
+    /// Absolute chunk coordinates
+    pub x: i32,
+    pub z: i32,
+    pub location: Location,
+    /// Last saved, in seconds since the epoch
+    pub timestamp: u32,
+    /// Bytes of chunk data, not counting padding to a sector. For external chunks, this is the size
+    /// of the `.mcc` file.
+    pub length: u32,
+    pub compression: Compression,
+    pub external: bool,
+    pub problems: Vec<ChunkProblem>,
+}
+
+pub struct Region {
+    path: PathBuf,
+    file: File,
+    file_len: u64,
+    /// Region coordinates, from the file name
+    pub x: i32,
+    pub z: i32,
+    pub locations: Vec<Location>,
+    pub timestamps: Vec<u32>,
+}
+
+/// Parses region coordinates out of `r.<x>.<z>.mca`.
+pub fn region_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut bits = name.strip_prefix("r.")?.strip_suffix(".mca")?.splitn(2, '.');
+    Some((bits.next()?.parse().ok()?, bits.next()?.parse().ok()?))
+}
+
+/// The region files in a directory, sorted by name.
+pub fn region_files(dir: &Path) -> Fallible<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if region_coords(&path).is_some() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl Region {
+    pub fn open(path: &Path) -> Fallible<Region> {
+        let (x, z) = region_coords(path)
+            .ok_or_else(|| format_err!("{} is not named like a region file", path.display()))?;
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut locations = Vec::with_capacity(CHUNKS);
+        let mut timestamps = Vec::with_capacity(CHUNKS);
+        // An empty file is a region nothing has been saved to yet
+        if file_len > 0 {
+            if file_len < SECTOR * HEADER_SECTORS as u64 {
+                bail!("{} is truncated, it's only {} bytes", path.display(), file_len);
+            }
+            for _ in 0..CHUNKS {
+                let entry = file.read_u32::<BE>()?;
+                locations.push(Location { offset: entry >> 8, sectors: entry as u8 });
+            }
+            for _ in 0..CHUNKS {
+                timestamps.push(file.read_u32::<BE>()?);
+            }
+        } else {
+            locations.resize(CHUNKS, Location { offset: 0, sectors: 0 });
+            timestamps.resize(CHUNKS, 0);
+        }
+        Ok(Region { path: path.to_path_buf(), file, file_len, x, z, locations, timestamps })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// Absolute chunk coordinates of a header index.
+    pub fn chunk_coords(&self, index: usize) -> (i32, i32) {
+        (self.x * 32 + (index % 32) as i32, self.z * 32 + (index / 32) as i32)
+    }
+
+    fn external_path(&self, index: usize) -> PathBuf {
+        let (x, z) = self.chunk_coords(index);
+        self.path.with_file_name(format!("c.{}.{}.mcc", x, z))
+    }
+
+    /// Checks every chunk in the header, without decompressing any of them.
+    pub fn chunks(&mut self) -> Fallible<Vec<ChunkInfo>> {
+        let mut chunks: Vec<ChunkInfo> = Vec::new();
+        for index in 0..CHUNKS {
+            let location = self.locations[index];
+            if location.is_empty() {
+                continue;
+            }
+            let (x, z) = self.chunk_coords(index);
+            let mut info = ChunkInfo {
+                index, x, z, location,
+                timestamp: self.timestamps[index],
+                length: 0,
+                compression: Compression::Unknown(0),
+                external: false,
+                problems: Vec::new(),
+            };
+            if location.offset < HEADER_SECTORS {
+                info.problems.push(ChunkProblem::InHeader);
+            }
+            let end = (location.offset as u64 + location.sectors as u64) * SECTOR;
+            if end > self.file_len {
+                info.problems.push(ChunkProblem::PastEnd);
+            }
+            for other in &chunks {
+                let o = other.location;
+                if location.offset < o.offset + o.sectors as u32 && o.offset < location.offset + location.sectors as u32 {
+                    info.problems.push(ChunkProblem::Overlaps(other.index));
+                }
+            }
+            // One bad read shouldn't hide the rest of the region
+            if info.problems.is_empty() {
+                if let Err(e) = self.check_data(&mut info) {
+                    info.problems.push(ChunkProblem::Unreadable(e.to_string()));
+                }
+            }
+            chunks.push(info);
+        }
+        Ok(chunks)
+    }
+
+    /// Reads the length and compression of a chunk whose location is sane.
+    fn check_data(&mut self, info: &mut ChunkInfo) -> Fallible<()> {
+        self.file.seek(SeekFrom::Start(info.location.offset as u64 * SECTOR))?;
+        let length = self.file.read_u32::<BE>()?;
+        let compression = self.file.read_u8()?;
+        info.external = compression & EXTERNAL != 0;
+        info.compression = Compression::from(compression & !EXTERNAL);
+        if let Compression::Unknown(id) = info.compression {
+            info.problems.push(ChunkProblem::UnknownCompression(id));
+        }
+        if info.external {
+            match fs::metadata(self.external_path(info.index)) {
+                Ok(meta) => info.length = meta.len() as u32,
+                Err(_) => info.problems.push(ChunkProblem::MissingExternal),
+            }
+        } else if length == 0 || length as u64 + 4 > info.location.sectors as u64 * SECTOR {
+            info.problems.push(ChunkProblem::BadLength(length));
+        } else {
+            info.length = length - 1;
+        }
+        Ok(())
+    }
+
+    /// Reads a chunk's raw compressed data and its compression type, if it exists.
+    pub fn read_raw(&mut self, index: usize) -> Fallible<Option<(Compression, Vec<u8>)>> {
+        let location = self.locations[index];
+        if location.is_empty() {
+            return Ok(None);
+        }
+        self.file.seek(SeekFrom::Start(location.offset as u64 * SECTOR))?;
+        let length = self.file.read_u32::<BE>()?;
+        let compression = self.file.read_u8()?;
+        if length == 0 || length as u64 + 4 > location.sectors as u64 * SECTOR {
+            bail!("chunk {} has a bad length of {} bytes", index, length);
+        }
+        let data = if compression & EXTERNAL != 0 {
+            fs::read(self.external_path(index))?
+        } else {
+            let mut data = vec![0; length as usize - 1];
+            self.file.read_exact(&mut data)?;
+            data
+        };
+        Ok(Some((Compression::from(compression & !EXTERNAL), data)))
+    }
+
+    /// Reads and decompresses a chunk's NBT, if it exists.
+    pub fn read_data(&mut self, index: usize) -> Fallible<Option<Vec<u8>>> {
+        match self.read_raw(index)? {
+            Some((compression, data)) => Ok(Some(decompress(compression, &data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads and parses a chunk, if it exists.
+    pub fn read_chunk<T: DeserializeOwned>(&mut self, index: usize) -> Fallible<Option<T>> {
+        match self.read_data(index)? {
+            Some(data) => Ok(Some(nbt::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Region {
+    /// The sectors a chunk needs, going by its length prefix.
+    fn sectors_needed(&mut self, index: usize) -> Fallible<u32> {
+        self.file.seek(SeekFrom::Start(self.locations[index].offset as u64 * SECTOR))?;
+        let length = self.file.read_u32::<BE>()? as u64;
+        Ok((length + 4).div_ceil(SECTOR) as u32)
+    }
+
+    /// How big the file would be after [`Region::rewrite`] with the same `keep`.
+    pub fn packed_len(&mut self, keep: impl Fn(usize) -> bool) -> Fallible<u64> {
+        let mut sectors = 0;
+        for index in 0..CHUNKS {
+            if !self.locations[index].is_empty() && keep(index) {
+                sectors += self.sectors_needed(index)? as u64;
+            }
+        }
+        Ok(if sectors == 0 { 0 } else { (HEADER_SECTORS as u64 + sectors) * SECTOR })
+    }
+
+    /// Rewrites the file with only the chunks `keep` accepts, packed together without any free
+    /// sectors, and returns the new size. The `.mcc` files of dropped chunks are deleted, and so is
+    /// a region left without any chunks.
+    ///
+    /// Check [`Region::chunks`] for problems first; a chunk that can't be read fails the rewrite.
+    ///
+    /// ```
+    /// use std::fs;
+    /// use localmc::region::{Location, Region, SECTOR};
+    /// let dir = std::env::temp_dir().join(format!("localmc-rewrite-{}", std::process::id()));
+    /// fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("r.0.0.mca");
+    ///
+    /// // Chunks 0 and 1 with a free sector between them, then chunk 2 and an external chunk 3
+    /// let chunks: [(u32, u8, u32, u8); 4] = [(2, 1, 100, 2), (4, 2, 5000, 2), (6, 1, 100, 2), (7, 1, 1, 2 | 128)];
+    /// let mut file = vec![0; 8 * SECTOR as usize];
+    /// for (index, &(offset, sectors, length, compression)) in chunks.iter().enumerate() {
+    ///     file[index * 4..][..4].copy_from_slice(&(offset << 8 | sectors as u32).to_be_bytes());
+    ///     file[4096 + index * 4..][..4].copy_from_slice(&(1000 + index as u32).to_be_bytes());
+    ///     let start = offset as usize * SECTOR as usize;
+    ///     file[start..][..4].copy_from_slice(&length.to_be_bytes());
+    ///     file[start + 4] = compression;
+    ///     file[start + 5..start + 4 + length as usize].fill(index as u8 + 1);
+    /// }
+    /// fs::write(&path, &file).unwrap();
+    /// fs::write(dir.join("c.3.0.mcc"), b"external").unwrap();
+    ///
+    /// let keep = |index| index < 2;
+    /// let mut region = Region::open(&path).unwrap();
+    /// let chunk_1 = region.read_raw(1).unwrap();
+    /// assert_eq!(region.packed_len(keep).unwrap(), 5 * SECTOR);
+    /// assert_eq!(region.rewrite(keep).unwrap(), 5 * SECTOR);
+    ///
+    /// let mut region = Region::open(&path).unwrap();
+    /// assert_eq!(region.file_len(), 5 * SECTOR);
+    /// assert_eq!(region.locations[..4], [
+    ///     Location { offset: 2, sectors: 1 },
+    ///     Location { offset: 3, sectors: 2 },
+    ///     Location { offset: 0, sectors: 0 },
+    ///     Location { offset: 0, sectors: 0 },
+    /// ]);
+    /// assert_eq!(region.timestamps[..4], [1000, 1001, 0, 0]);
+    /// assert_eq!(region.read_raw(1).unwrap(), chunk_1);
+    /// assert!(region.chunks().unwrap().iter().all(|c| c.problems.is_empty()));
+    /// assert!(!dir.join("c.3.0.mcc").exists());
+    ///
+    /// // Nothing left, nothing kept
+    /// assert_eq!(region.rewrite(|_| false).unwrap(), 0);
+    /// assert!(!path.exists());
+    /// fs::remove_dir(&dir).unwrap();
+    /// ```
+    pub fn rewrite(self, keep: impl Fn(usize) -> bool) -> Fallible<u64> {
+        self.stage_rewrite(keep)?.commit()
+    }
+
+    /// Does everything [`Region::rewrite`] does except replacing the file: the packed copy is
+    /// written next to it, and only takes its place on [`StagedRewrite::commit`]. Dropping it
+    /// instead leaves everything as it was, so several files can be rewritten all or nothing.
+    pub fn stage_rewrite(mut self, keep: impl Fn(usize) -> bool) -> Fallible<StagedRewrite> {
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let tmp = self.path.with_file_name(format!(".{}.tmp", file_name));
+        let mut locations = vec![Location { offset: 0, sectors: 0 }; CHUNKS];
+        let mut timestamps = vec![0; CHUNKS];
+        let mut body = Vec::new();
+        // Only deleted once the new file is in place, so a failed rewrite loses nothing
+        let mut dropped = Vec::new();
+        let mut next = HEADER_SECTORS;
+        for index in 0..CHUNKS {
+            if self.locations[index].is_empty() {
+                continue;
+            }
+            if !keep(index) {
+                let external = self.external_path(index);
+                if external.exists() {
+                    dropped.push(external);
+                }
+                continue;
+            }
+            let sectors = self.sectors_needed(index)?;
+            if sectors > u8::MAX as u32 {
+                bail!("chunk {} is too big for a region file", index);
+            }
+            let mut data = vec![0; (sectors as u64 * SECTOR) as usize];
+            self.file.seek(SeekFrom::Start(self.locations[index].offset as u64 * SECTOR))?;
+            self.file.read_exact(&mut data)?;
+            body.push(data);
+            locations[index] = Location { offset: next, sectors: sectors as u8 };
+            timestamps[index] = self.timestamps[index];
+            next += sectors;
+        }
+
+        let mut staged = StagedRewrite { path: self.path, tmp: None, dropped, len: 0 };
+        if body.is_empty() {
+            return Ok(staged);
+        }
+        // Set first, so the temp file is cleaned up if writing it fails
+        staged.tmp = Some(tmp.clone());
+        staged.len = next as u64 * SECTOR;
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        for location in &locations {
+            w.write_u32::<BE>(location.offset << 8 | location.sectors as u32)?;
+        }
+        for timestamp in &timestamps {
+            w.write_u32::<BE>(*timestamp)?;
+        }
+        for data in &body {
+            w.write_all(data)?;
+        }
+        w.flush()?;
+        w.get_ref().sync_all()?;
+        Ok(staged)
+    }
+}
+
+/// A rewritten region waiting to replace the original, from [`Region::stage_rewrite`].
+pub struct StagedRewrite {
+    path: PathBuf,
+    /// The packed copy, or `None` if no chunks are left and the region is to be deleted
+    tmp: Option<PathBuf>,
+    /// `.mcc` files of chunks that aren't kept
+    dropped: Vec<PathBuf>,
+    len: u64,
+}
+
+impl StagedRewrite {
+    /// The size the file will be.
+    pub fn new_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Replaces the original file, and deletes the `.mcc` files of dropped chunks. Returns the new
+    /// size.
+    pub fn commit(mut self) -> Fallible<u64> {
+        match &self.tmp {
+            Some(tmp) => fs::rename(tmp, &self.path)?,
+            None => fs::remove_file(&self.path)?,
+        }
+        self.tmp = None;
+        for external in std::mem::take(&mut self.dropped) {
+            fs::remove_file(external)?;
+        }
+        Ok(self.len)
+    }
+}
+
+impl Drop for StagedRewrite {
+    fn drop(&mut self) {
+        if let Some(tmp) = self.tmp.take() {
+            let _ = fs::remove_file(tmp);
+        }
+    }
+}
+
+pub fn decompress(compression: Compression, data: &[u8]) -> Fallible<Vec<u8>> {
+    let mut out = Vec::new();
+    match compression {
+        Compression::Gzip => { GzDecoder::new(data).read_to_end(&mut out)?; },
+        Compression::Zlib => { ZlibDecoder::new(data).read_to_end(&mut out)?; },
+        Compression::None => out.extend_from_slice(data),
+        Compression::Lz4 => out = decompress_lz4_blocks(data)?,
+        Compression::Unknown(id) => bail!("unknown compression type {}", id),
+    }
+    Ok(out)
+}
+
+/// Minecraft's LZ4 chunks use the framing of lz4-java's `LZ4BlockOutputStream`: a series of
+/// blocks each with a header of its own, ending with an empty one.
+fn decompress_lz4_blocks(mut data: &[u8]) -> Fallible<Vec<u8>> {
+    const MAGIC: &[u8] = b"LZ4Block";
+    const RAW: u8 = 0x10;
+    const LZ4: u8 = 0x20;
+    /// The biggest block lz4-java writes
+    const MAX_BLOCK: usize = 32 * 1024 * 1024;
+    let mut out = Vec::new();
+    loop {
+        if !data.starts_with(MAGIC) {
+            bail!("LZ4 block is missing its header");
+        }
+        data = &data[MAGIC.len()..];
+        let token = data.read_u8()?;
+        let compressed_len = data.read_u32::<LE>()? as usize;
+        let len = data.read_u32::<LE>()? as usize;
+        // lz4-java's xxhash32 of the block. Corruption shows up as bad NBT anyway, so it isn't
+        // worth a hashing dependency to check.
+        let _checksum = data.read_u32::<LE>()?;
+        if compressed_len > data.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let (block, rest) = data.split_at(compressed_len);
+        data = rest;
+        if len == 0 {
+            return Ok(out);
+        }
+        // Checked before decompressing, since the length is what gets allocated. LZ4 can't
+        // expand anything more than 255 times.
+        if len > MAX_BLOCK || len > compressed_len.saturating_mul(255) {
+            bail!("LZ4 block says it's {} bytes, from {} compressed", len, compressed_len);
+        }
+        match token & 0xf0 {
+            RAW => out.extend_from_slice(block),
+            LZ4 => out.extend(lz4_flex::block::decompress(block, len)?),
+            method => bail!("unknown LZ4 block method {:#x}", method),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Reading the world's `level.dat`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use failure::Fallible;
+use mcproto_min_async::nbt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+struct LevelDat {
+    #[serde(rename = "Data")]
+    data: Level,
+}
+
+/// The interesting parts of `level.dat`. Fields that older versions don't write are optional.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Level {
+    pub level_name: String,
+    /// The seed before 1.16
+    pub random_seed: Option<i64>,
+    /// Where the seed lives since 1.16
+    pub world_gen_settings: Option<WorldGenSettings>,
+    pub game_type: i32,
+    pub difficulty: Option<i8>,
+    #[serde(rename = "hardcore", default)]
+    pub hardcore: bool,
+    /// The spawn point before 1.21.9
+    pub spawn_x: Option<i32>,
+    pub spawn_y: Option<i32>,
+    pub spawn_z: Option<i32>,
+    /// Where the spawn point lives since 1.21.9
+    #[serde(rename = "spawn")]
+    pub spawn: Option<Spawn>,
+    pub time: i64,
+    pub day_time: Option<i64>,
+    #[serde(default)]
+    pub game_rules: BTreeMap<String, String>,
+    pub data_version: Option<i32>,
+    pub data_packs: Option<DataPacks>,
+    pub version: Option<Version>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spawn {
+    pub dimension: Option<String>,
+    pub pos: nbt::IntArray,
+    pub yaw: Option<f32>,
+    pub pitch: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldGenSettings {
+    pub seed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DataPacks {
+    #[serde(default)]
+    pub enabled: Vec<String>,
+    #[serde(default)]
+    pub disabled: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Version {
+    pub id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub snapshot: bool,
+}
+
+impl Level {
+    pub fn seed(&self) -> Option<i64> {
+        self.world_gen_settings.as_ref().map(|s| s.seed).or(self.random_seed)
+    }
+
+    /// The spawn point, from whichever layout this version wrote.
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use mcproto_min_async::nbt::{self, Compression, Tag};
+    /// use localmc::world::Level;
+    /// let level = |spawn: &[(&str, Tag)]| {
+    ///     let mut fields = BTreeMap::new();
+    ///     fields.insert("LevelName".to_string(), Tag::String("world".to_string()));
+    ///     fields.insert("GameType".to_string(), Tag::Int(0));
+    ///     fields.insert("Time".to_string(), Tag::Long(0));
+    ///     for (name, tag) in spawn {
+    ///         fields.insert(name.to_string(), tag.clone());
+    ///     }
+    ///     let data = nbt::to_vec("", &Tag::Compound(fields), Compression::None).unwrap();
+    ///     nbt::from_slice::<Level>(&data).unwrap()
+    /// };
+    ///
+    /// let old = level(&[("SpawnX", Tag::Int(10)), ("SpawnY", Tag::Int(64)), ("SpawnZ", Tag::Int(-3))]);
+    /// assert_eq!(old.spawn_pos(), Some([10, 64, -3]));
+    ///
+    /// let mut spawn = BTreeMap::new();
+    /// spawn.insert("dimension".to_string(), Tag::String("minecraft:overworld".to_string()));
+    /// spawn.insert("pos".to_string(), Tag::IntArray(vec![-5, 70, 8]));
+    /// spawn.insert("yaw".to_string(), Tag::Float(0.0));
+    /// spawn.insert("pitch".to_string(), Tag::Float(0.0));
+    /// let new = level(&[("spawn", Tag::Compound(spawn))]);
+    /// assert_eq!(new.spawn_pos(), Some([-5, 70, 8]));
+    ///
+    /// assert_eq!(level(&[]).spawn_pos(), None);
+    /// ```
+    pub fn spawn_pos(&self) -> Option<[i32; 3]> {
+        if let Some(&[x, y, z]) = self.spawn.as_ref().map(|s| &s.pos[..]) {
+            return Some([x, y, z]);
+        }
+        Some([self.spawn_x?, self.spawn_y?, self.spawn_z?])
+    }
+
+    pub fn game_type_name(&self) -> &'static str {
+        game_type_name(self.game_type)
+    }
+
+    pub fn difficulty_name(&self) -> &'static str {
+        match self.difficulty {
+            Some(0) => "peaceful",
+            Some(1) => "easy",
+            Some(2) | None => "normal",
+            Some(3) => "hard",
+            Some(_) => "unknown",
+        }
+    }
+}
+
+pub fn game_type_name(id: i32) -> &'static str {
+    match id {
+        0 => "survival",
+        1 => "creative",
+        2 => "adventure",
+        3 => "spectator",
+        _ => "unknown",
+    }
+}
+
+/// The world directory, according to server.properties.
+pub fn world_dir(root: &Path, props: &HashMap<String, String>) -> PathBuf {
+    root.join(props.get("level-name").map(String::as_str).unwrap_or("world"))
+}
+
+/// Where a dimension keeps its region files and the like. Takes a dimension name like
+/// `minecraft:the_nether`; the namespace can be left off for vanilla dimensions.
+pub fn dimension_dir(world: &Path, dimension: &str) -> PathBuf {
+    match dimension.strip_prefix("minecraft:").unwrap_or(dimension) {
+        "overworld" => world.to_path_buf(),
+        "the_nether" | "nether" => world.join("DIM-1"),
+        "the_end" | "end" => world.join("DIM1"),
+        _ => {
+            let mut bits = dimension.splitn(2, ':');
+            let (ns, path) = match (bits.next(), bits.next()) {
+                (Some(ns), Some(path)) => (ns, path),
+                _ => ("minecraft", dimension),
+            };
+            world.join("dimensions").join(ns).join(path)
+        }
+    }
+}
+
+pub fn read_level(world: &Path) -> Fallible<Level> {
+    let level: LevelDat = nbt::from_reader(File::open(world.join("level.dat"))?)?;
+    Ok(level.data)
+}
+
+/// Whether a server has the world open. Minecraft holds a lock on `session.lock` for as long as
+/// it has the world loaded, so this works even when the server isn't reachable over the network.
+pub fn session_locked(world: &Path) -> Fallible<bool> {
+    let file = match OpenOptions::new().read(true).write(true).open(world.join("session.lock")) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    // Java's FileLock is a POSIX record lock, so ask whether one would conflict with ours
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(lock.l_type as libc::c_int != libc::F_UNLCK)
+}
@@ -0,0 +1,321 @@
+//! The key exchange and session server checks that online-mode logins go through.
+//!
+//! The client and server agree on a shared secret, encrypted with the server's RSA key, and then
+//! both tell the session server about a hash of it: the client says it's joining, and the server
+//! asks whether it has. Only plain HTTP session servers are supported, which is enough for
+//! [`MockSessionServer`], and a real server can be pointed at one with
+//! `-Dminecraft.api.session.host=http://<host>:<port>`.
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use tokio::io;
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// Where the real session server is. It needs HTTPS, which this doesn't do.
+pub const MOJANG_SESSION_HOST: &str = "https://sessionserver.mojang.com";
+
+/// The hash both sides give the session server, a SHA-1 printed as a signed number in hex.
+///
+/// Test vectors from wiki.vg:
+///
+/// ```
+/// use mcproto_min_async::auth::server_hash;
+/// assert_eq!(server_hash("Notch", &[], &[]), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+/// assert_eq!(server_hash("jeb_", &[], &[]), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+/// assert_eq!(server_hash("simon", &[], &[]), "88e16a1019277b15d58faf0541e11910eb756f6");
+/// ```
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hash: [u8; 20] = Sha1::new()
+        .chain_update(server_id.as_bytes())
+        .chain_update(shared_secret)
+        .chain_update(public_key)
+        .finalize()
+        .into();
+    let negative = hash[0] & 0x80 != 0;
+    if negative {
+        // Two's complement, to print the magnitude
+        let mut carry = true;
+        for b in hash.iter_mut().rev() {
+            *b = !*b;
+            if carry {
+                let (v, c) = b.overflowing_add(1);
+                *b = v;
+                carry = c;
+            }
+        }
+    }
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+    let hex = hex.trim_start_matches('0');
+    format!("{}{}", if negative { "-" } else { "" }, hex)
+}
+
+fn bad_key(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Bad key exchange: {}", e))
+}
+
+/// Encrypts part of the key exchange with the server's public key, as sent in Encryption Request.
+pub fn encrypt(public_key: &[u8], data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let key = RsaPublicKey::from_public_key_der(public_key).map_err(bad_key)?;
+    key.encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, data).map_err(bad_key)
+}
+
+/// The server's half of the key exchange. Servers make a new one each time they start.
+pub struct ServerKey {
+    key: RsaPrivateKey,
+    der: Vec<u8>,
+}
+
+impl ServerKey {
+    pub fn generate() -> Result<ServerKey, io::Error> {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).map_err(bad_key)?;
+        let der = key.to_public_key().to_public_key_der().map_err(bad_key)?.into_vec();
+        Ok(ServerKey { key, der })
+    }
+    /// The public key, in the DER form Encryption Request sends.
+    pub fn public_der(&self) -> &[u8] {
+        &self.der
+    }
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        self.key.decrypt(Pkcs1v15Encrypt, data).map_err(bad_key)
+    }
+}
+
+/// A UUID the way the session server writes it, in hex without dashes.
+pub fn uuid_hex(uuid: u128) -> String {
+    format!("{:032x}", uuid)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A player, as the session server describes them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// See [`uuid_hex`]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<Property>,
+}
+
+/// What a client needs to log in to an online-mode server.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub uuid: u128,
+    pub access_token: String,
+    /// Like `http://localhost:8080`, see [`MOJANG_SESSION_HOST`]
+    pub session_host: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JoinRequest {
+    access_token: String,
+    selected_profile: String,
+    server_id: String,
+}
+
+/// Percent-encodes a query string parameter.
+fn escape(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        b => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) if hex.len() == 2 => out.push(b),
+                    _ => { out.push(b'%'); out.extend(hex); },
+                }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn http_error(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, format!("Session server: {}", e))
+}
+
+/// Tells the session server the client is joining the server with this hash.
+pub async fn join(account: &Account, server_hash: &str) -> Result<(), io::Error> {
+    let body = serde_json::to_vec(&JoinRequest {
+        access_token: account.access_token.clone(),
+        selected_profile: uuid_hex(account.uuid),
+        server_id: server_hash.to_string(),
+    })?;
+    let req = Request::post(format!("{}/session/minecraft/join", account.session_host))
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .map_err(http_error)?;
+    let resp = hyper::Client::new().request(req).await.map_err(http_error)?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("Session server refused the join: {}", resp.status())))
+    }
+}
+
+/// Asks the session server whether a player joined with this hash, and who they are if so.
+pub async fn has_joined(session_host: &str, name: &str, server_hash: &str) -> Result<Option<Profile>, io::Error> {
+    let uri = format!("{}/session/minecraft/hasJoined?username={}&serverId={}",
+        session_host, escape(name), escape(server_hash));
+    let uri: hyper::Uri = uri.parse().map_err(http_error)?;
+    let resp = hyper::Client::new().get(uri).await.map_err(http_error)?;
+    if resp.status() != StatusCode::OK {
+        return Ok(None);
+    }
+    let body = hyper::body::to_bytes(resp.into_body()).await.map_err(http_error)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+#[derive(Default)]
+struct Sessions {
+    /// Access token to profile
+    accounts: HashMap<String, Profile>,
+    /// Name to the hash they last joined with
+    joined: HashMap<String, (String, Profile)>,
+}
+
+/// A stand-in for the session server, for testing online-mode logins without real accounts. It
+/// only implements `join` and `hasJoined`.
+///
+/// A whole online-mode login, with the client joining and the server checking:
+///
+/// ```
+/// use std::net::TcpListener;
+/// use mcproto_min_async::auth::{self, Account, MockSessionServer};
+/// use mcproto_min_async::protocol::{login, Handshake, Login, ProtocolState};
+/// use mcproto_min_async::server::{Client, Connection, ConnectionSettings};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// let sessions = MockSessionServer::new();
+/// let uuid = 0x069a79f4_44e9_4726_a5be_fca90e38aaf5;
+/// let access_token = sessions.add_account("Notch", uuid);
+/// let listener = TcpListener::bind("127.0.0.1:0")?;
+/// let session_host = format!("http://{}", listener.local_addr()?);
+/// tokio::spawn(sessions.clone().serve(listener));
+///
+/// let (ours, theirs) = tokio::io::duplex(4096);
+/// let host = session_host.clone();
+/// let server = tokio::spawn(async move {
+///     let mut client = match Connection::with_settings(theirs, &ConnectionSettings::new()).await? {
+///         Connection::Login(client) => client,
+///         Connection::Status(_) => panic!("expected a login"),
+///     };
+///     let (name, _) = client.read_login_start().await?;
+///     let key = auth::ServerKey::generate()?;
+///     let profile = client.encrypt(&key, &name, Some(&host)).await?.unwrap();
+///     client.login_success(&login::Success { uuid, name: profile.name.clone() }).await?;
+///     Ok::<_, std::io::Error>(profile)
+/// });
+///
+/// let account = Account { uuid, access_token, session_host: session_host.clone() };
+/// let client = Client::create(ours, Handshake);
+/// let mut client = client.handshake(766, "localhost", 25565, ProtocolState::Login, Login).await?;
+/// let success = client.login("Notch", Some(&account)).await?;
+/// assert_eq!((success.uuid, success.name.as_str()), (uuid, "Notch"));
+/// assert_eq!(server.await??.id, auth::uuid_hex(uuid));
+///
+/// // Names are escaped, so they can't add parameters of their own
+/// let odd = Account { uuid: 1, access_token: sessions.add_account("a b&serverId=x#", 1), session_host: session_host.clone() };
+/// auth::join(&odd, "-1a").await?;
+/// assert!(auth::has_joined(&session_host, "a b&serverId=x#", "-1a").await?.is_some());
+/// assert!(auth::has_joined(&session_host, "a b", "x").await?.is_none());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default, Clone)]
+pub struct MockSessionServer {
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+impl MockSessionServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes up an account that can join, returning it with its access token.
+    pub fn add_account(&self, name: &str, uuid: u128) -> String {
+        let token = uuid_hex(rand::random());
+        let profile = Profile { id: uuid_hex(uuid), name: name.to_string(), properties: Vec::new() };
+        self.sessions.lock().unwrap().accounts.insert(token.clone(), profile);
+        token
+    }
+
+    fn handle(&self, method: &Method, path: &str, query: &str, body: &[u8]) -> Response<Body> {
+        let status = |s| Response::builder().status(s).body(Body::empty()).unwrap();
+        let mut sessions = self.sessions.lock().unwrap();
+        match (method, path) {
+            (&Method::POST, "/session/minecraft/join") => {
+                let req: JoinRequest = match serde_json::from_slice(body) {
+                    Ok(r) => r,
+                    Err(_) => return status(StatusCode::BAD_REQUEST),
+                };
+                let profile = match sessions.accounts.get(&req.access_token) {
+                    Some(p) if p.id == req.selected_profile => p.clone(),
+                    _ => return status(StatusCode::FORBIDDEN),
+                };
+                sessions.joined.insert(profile.name.clone(), (req.server_id, profile));
+                status(StatusCode::NO_CONTENT)
+            }
+            (&Method::GET, "/session/minecraft/hasJoined") => {
+                let params: HashMap<String, String> = query.split('&').filter_map(|p| {
+                    let mut bits = p.splitn(2, '=');
+                    Some((unescape(bits.next()?), unescape(bits.next()?)))
+                }).collect();
+                let joined = params.get("username").and_then(|name| sessions.joined.get(name));
+                match joined {
+                    Some((hash, profile)) if Some(hash) == params.get("serverId") => {
+                        Response::new(Body::from(serde_json::to_vec(profile).unwrap()))
+                    }
+                    _ => status(StatusCode::NO_CONTENT),
+                }
+            }
+            _ => status(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// Answers requests on the listener until the future is dropped.
+    pub async fn serve(self, listener: TcpListener) -> Result<(), io::Error> {
+        let make_service = make_service_fn(move |_| {
+            let server = self.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                    let server = server.clone();
+                    async move {
+                        let method = req.method().clone();
+                        let path = req.uri().path().to_string();
+                        let query = req.uri().query().unwrap_or("").to_string();
+                        let body = hyper::body::to_bytes(req.into_body()).await?;
+                        Ok::<_, hyper::Error>(server.handle(&method, &path, &query, &body))
+                    }
+                }))
+            }
+        });
+        hyper::Server::from_tcp(listener).map_err(http_error)?
+            .serve(make_service).await.map_err(http_error)
+    }
+}
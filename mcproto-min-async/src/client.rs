@@ -0,0 +1,263 @@
+//! Talking to a server as a client.
+//!
+//! [`connect`] opens a connection and does the handshake for a status ping, and
+//! [`connect_login`] does the same for logging in, after which it's up to
+//! [`Client::login`](crate::server::Client::login). Everything is done under a timeout, set with
+//! [`Settings::set_timeout`].
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use mcproto_min_async::client;
+//! let mut server = client::connect("mc.example.com").await?;
+//! let status = server.status().await?;
+//! println!("{} ({}), {:?}", status.description, status.version.name, server.ping().await?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::io::{self, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::protocol::{self, status, Handshake, Login, ProtocolState, Status};
+use crate::proxy_protocol;
+use crate::server::{Client, Transport};
+use crate::text::TextComponent;
+
+pub const DEFAULT_PORT: u16 = 25565;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The protocol version to ping with when there isn't one to match. Servers answer status pings
+/// from any version, and say which one they are.
+pub const ANY_VERSION: i32 = -1;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    version: i32,
+    host: Option<(String, u16)>,
+    timeout: Duration,
+    proxy_protocol: Option<u8>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { version: ANY_VERSION, host: None, timeout: DEFAULT_TIMEOUT, proxy_protocol: None }
+    }
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The protocol version to say we speak. The default, [`ANY_VERSION`], is only good for
+    /// status pings; logins need the server's own.
+    pub fn set_version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+    /// The address to give in the handshake, instead of the one connected to. Proxies in front of
+    /// several servers go by it.
+    pub fn set_host(mut self, host: &str, port: u16) -> Self {
+        self.host = Some((host.to_string(), port));
+        self
+    }
+    /// How long connecting can take, and then each request after it.
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Starts the connection with a PROXY protocol header, version 1 or 2, for servers that
+    /// expect to be behind a load balancer.
+    pub fn send_proxy_protocol(mut self, version: u8) -> Self {
+        self.proxy_protocol = Some(version);
+        self
+    }
+}
+
+/// Splits `host`, `host:port` or `[v6]:port`, with the port defaulting to [`DEFAULT_PORT`].
+/// SRV records aren't looked up.
+///
+/// ```
+/// use mcproto_min_async::client::split_addr;
+/// assert_eq!(split_addr("mc.example.com").unwrap(), ("mc.example.com", 25565));
+/// assert_eq!(split_addr("127.0.0.1:25599").unwrap(), ("127.0.0.1", 25599));
+/// assert_eq!(split_addr("[::1]:25599").unwrap(), ("::1", 25599));
+/// assert_eq!(split_addr("[::1]").unwrap(), ("::1", 25565));
+/// assert!(split_addr("mc.example.com:http").is_err());
+/// ```
+pub fn split_addr(addr: &str) -> Result<(&str, u16), io::Error> {
+    let bad = || io::Error::new(io::ErrorKind::InvalidInput, format!("Bad server address {:?}", addr));
+    let (host, port) = match addr.rfind(':') {
+        // Not part of a bare IPv6 address
+        Some(i) if !addr[..i].contains(':') || addr[..i].ends_with(']') => (&addr[..i], Some(&addr[i + 1..])),
+        _ => (addr, None),
+    };
+    let port = match port {
+        Some(p) => p.parse().map_err(|_| bad())?,
+        None => DEFAULT_PORT,
+    };
+    let host = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    if host.is_empty() {
+        return Err(bad());
+    }
+    Ok((host, port))
+}
+
+async fn timed<T>(timeout: Duration, f: impl Future<Output = Result<T, io::Error>>) -> Result<T, io::Error> {
+    tokio::time::timeout(timeout, f).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("No answer after {:?}", timeout)))?
+}
+
+/// Connects and sends the handshake.
+async fn open<P: protocol::Protocol>(
+    addr: &str, settings: &Settings, next_state: ProtocolState, protocol: P,
+) -> Result<Client<P>, io::Error> {
+    let (host, port) = split_addr(addr)?;
+    timed(settings.timeout, async {
+        let mut stream = TcpStream::connect((host, port)).await?;
+        if let Some(version) = settings.proxy_protocol {
+            let (local, peer): (SocketAddr, SocketAddr) = (stream.local_addr()?, stream.peer_addr()?);
+            let header = match version {
+                1 => proxy_protocol::header_v1(local, peer),
+                _ => proxy_protocol::header_v2(local, peer),
+            };
+            stream.write_all(&header).await?;
+        }
+        let (host, port) = match settings.host {
+            Some((ref host, port)) => (host.as_str(), port),
+            None => (host, port),
+        };
+        Client::create(stream, Handshake).handshake(settings.version, host, port, next_state, protocol).await
+    }).await
+}
+
+/// Connects for a status ping.
+pub async fn connect(addr: &str) -> Result<StatusClient, io::Error> {
+    connect_with_settings(addr, &Settings::new()).await
+}
+
+pub async fn connect_with_settings(addr: &str, settings: &Settings) -> Result<StatusClient, io::Error> {
+    let client = open(addr, settings, ProtocolState::Status, Status).await?;
+    Ok(StatusClient::new(client, settings.timeout))
+}
+
+/// Connects for logging in. Set the version to the server's, which [`StatusResponse`] has.
+pub async fn connect_login(addr: &str, settings: &Settings) -> Result<Client<Login>, io::Error> {
+    open(addr, settings, ProtocolState::Login, Login).await
+}
+
+/// A connection that's had the handshake for a status ping. Servers answer one status request
+/// and then one ping, in that order, and close the connection after the ping.
+pub struct StatusClient<S = TcpStream> {
+    client: Client<Status, S>,
+    timeout: Duration,
+}
+
+impl<S: Transport> StatusClient<S> {
+    pub fn new(client: Client<Status, S>, timeout: Duration) -> Self {
+        StatusClient { client, timeout }
+    }
+
+    /// The status, as the server sent it.
+    pub async fn status_json(&mut self) -> Result<String, io::Error> {
+        let client = &mut self.client;
+        timed(self.timeout, async move {
+            client.write(&status::Serverbound::Request).await?;
+            match client.read_cb().await? {
+                status::Clientbound::ServerListResp { data } => Ok(data),
+                p => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected packet {:?}", p))),
+            }
+        }).await
+    }
+
+    pub async fn status(&mut self) -> Result<StatusResponse, io::Error> {
+        Ok(serde_json::from_str(&self.status_json().await?)?)
+    }
+
+    /// How long the server takes to answer.
+    pub async fn ping(&mut self) -> Result<Duration, io::Error> {
+        let client = &mut self.client;
+        timed(self.timeout, async move {
+            let payload: u64 = rand::random();
+            let start = Instant::now();
+            client.write(&status::Serverbound::Ping(payload)).await?;
+            match client.read_cb().await? {
+                status::Clientbound::Pong(p) if p == payload => Ok(start.elapsed()),
+                p => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected packet {:?}", p))),
+            }
+        }).await
+    }
+
+    pub fn into_inner(self) -> Client<Status, S> {
+        self.client
+    }
+}
+
+/// What a server says about itself in answer to a status ping.
+///
+/// ```
+/// use mcproto_min_async::client::StatusResponse;
+/// let status: StatusResponse = serde_json::from_str(r#"{
+///     "version": {"name": "1.20.6", "protocol": 766},
+///     "players": {"max": 20, "online": 1, "sample": [{"name": "Alex", "id": "ec561538-f3fd-461d-aff5-086b22154bce"}]},
+///     "description": "§aA Minecraft Server",
+///     "enforcesSecureChat": true
+/// }"#).unwrap();
+/// assert_eq!(status.version.protocol, 766);
+/// assert_eq!(status.players.unwrap().sample[0].name, "Alex");
+/// assert_eq!(status.description.to_plain(), "§aA Minecraft Server");
+/// assert_eq!(status.extra["enforcesSecureChat"], true);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub version: StatusVersion,
+    /// Left out by servers that hide their player count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub players: Option<StatusPlayers>,
+    /// The MOTD. Servers still put legacy `§` codes in it as often as not.
+    #[serde(default)]
+    pub description: TextComponent,
+    /// The server's icon, as a `data:image/png;base64,` URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    /// Anything else, like `enforcesSecureChat` and mod loader info
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusPlayers {
+    pub max: i64,
+    pub online: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<StatusPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusPlayer {
+    pub name: String,
+    pub id: String,
+}
+
+impl StatusResponse {
+    /// The icon as a PNG, if there is one.
+    pub fn favicon_png(&self) -> Option<Result<Vec<u8>, io::Error>> {
+        let url = self.favicon.as_ref()?;
+        Some(match url.strip_prefix("data:image/png;base64,") {
+            // Old servers wrapped the base64 in lines
+            Some(encoded) => base64::decode(encoded.split_whitespace().collect::<String>())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "The icon isn't a base64 PNG")),
+        })
+    }
+}
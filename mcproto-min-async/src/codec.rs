@@ -5,24 +5,136 @@ use flate2::{Compression, write::ZlibEncoder, read::ZlibDecoder};
 
 use bytes::{Bytes, BytesMut, BufMut};
 
+use aes::Aes128;
+use cfb8::cipher::{KeyIvInit, BlockEncryptMut, BlockDecryptMut, generic_array::GenericArray};
+
+use serde::Deserialize;
+
 use crate::{de, varint, protocol::Protocol};
 
 // TODO: warn that you can't use it for both
 
-#[derive(Default)]
+/// The AES-128-CFB8 stream cipher the protocol switches to after the key exchange. Both directions
+/// use the shared secret as the key and the IV, each with its own state.
+///
+/// Test vectors from NIST SP 800-38A (F.3.7 and F.3.8):
+///
+/// ```
+/// use mcproto_min_async::codec::Cipher;
+/// let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+/// let iv = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+/// let plain = [0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a, 0xae, 0x2d];
+/// let cipher = [0x3b, 0x79, 0x42, 0x4c, 0x9c, 0x0d, 0xd4, 0x36, 0xba, 0xce, 0x9e, 0x0e, 0xd4, 0x58, 0x6a, 0x4f, 0x32, 0xb9];
+///
+/// let mut data = plain;
+/// // Split up, since it has to work on however much of the stream has arrived
+/// let mut enc = Cipher::new(&key, &iv);
+/// enc.encrypt(&mut data[..5]);
+/// enc.encrypt(&mut data[5..]);
+/// assert_eq!(data, cipher);
+///
+/// let mut dec = Cipher::new(&key, &iv);
+/// dec.decrypt(&mut data[..11]);
+/// dec.decrypt(&mut data[11..]);
+/// assert_eq!(data, plain);
+/// ```
+pub struct Cipher {
+    enc: cfb8::Encryptor<Aes128>,
+    dec: cfb8::Decryptor<Aes128>,
+}
+
+impl Cipher {
+    pub fn new(key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        Cipher {
+            enc: cfb8::Encryptor::new(key.into(), iv.into()),
+            dec: cfb8::Decryptor::new(key.into(), iv.into()),
+        }
+    }
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for b in data {
+            self.enc.encrypt_block_mut(GenericArray::from_mut_slice(std::slice::from_mut(b)));
+        }
+    }
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for b in data {
+            self.dec.decrypt_block_mut(GenericArray::from_mut_slice(std::slice::from_mut(b)));
+        }
+    }
+}
+
+/// The biggest packet the protocol allows, the most a 3-byte varint can say.
+pub const MAX_FRAME_LEN: usize = 2097151;
+/// The biggest packet vanilla will decompress.
+pub const MAX_UNCOMPRESSED_LEN: usize = 8388608;
+
+/// Frames packets, and compresses and encrypts them once the connection gets to that.
+///
+/// Lengths are checked before anything is buffered for them:
+///
+/// ```
+/// use bytes::BytesMut;
+/// use tokio_util::codec::Decoder;
+/// use mcproto_min_async::codec::Codec;
+/// let mut codec = Codec::new();
+/// // Not all there yet
+/// assert!(codec.decode(&mut BytesMut::from(&[0x05, 0x00][..])).unwrap().is_none());
+/// // 2097152 bytes, one too many
+/// assert!(codec.decode(&mut BytesMut::from(&[0x80, 0x80, 0x80, 0x01][..])).is_err());
+/// // Negative
+/// assert!(codec.decode(&mut BytesMut::from(&[0xff, 0xff, 0xff, 0xff, 0x0f][..])).is_err());
+/// ```
 pub struct Codec {
     compression: Option<i32>,
+    cipher: Option<Cipher>,
+    /// How much of the start of the read buffer has been decrypted already
+    decrypted: usize,
+    max_frame_len: usize,
+    max_uncompressed_len: usize,
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec {
+            compression: None,
+            cipher: None,
+            decrypted: 0,
+            max_frame_len: MAX_FRAME_LEN,
+            max_uncompressed_len: MAX_UNCOMPRESSED_LEN,
+        }
+    }
+}
+
+/// The most buffer space reserved ahead of a packet's bytes arriving.
+const RESERVE_CHUNK: usize = 8192;
+
+fn too_big(what: &str, len: i64, max: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} of {} bytes is over the limit of {}", what, len, max))
 }
 
 impl Codec {
     pub fn new() -> Self {
         Self::default()
     }
+    /// Refuses packets longer than this, as sent. Defaults to [`MAX_FRAME_LEN`].
+    pub fn set_max_frame_len(&mut self, len: usize) {
+        self.max_frame_len = len;
+    }
+    /// Refuses compressed packets that say they're longer than this once decompressed. Defaults
+    /// to [`MAX_UNCOMPRESSED_LEN`].
+    pub fn set_max_uncompressed_len(&mut self, len: usize) {
+        self.max_uncompressed_len = len;
+    }
     /// Enables compression with the specified threshold. It is not advisable to re-specify the
     /// compression limit.
     pub fn set_compression(&mut self, limit: i32) {
         self.compression = Some(limit);
     }
+    /// Encrypts everything from here on with the shared secret from the key exchange. Anything
+    /// already buffered that hasn't been decoded yet is taken to be encrypted too.
+    pub fn set_encryption(&mut self, shared_secret: &[u8; 16]) {
+        self.cipher = Some(Cipher::new(shared_secret, shared_secret));
+        self.decrypted = 0;
+    }
 }
 
 impl Decoder for Codec {
@@ -30,21 +142,50 @@ impl Decoder for Codec {
     type Error = io::Error;
     fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<RawPacket>> {
         let buf = src;
-        let (len, off) = if let Some(c) = varint::read_slice(&buf[..]) { c }
-            else { return Ok(None) };
-        if buf.len() < len as usize + off {
+        if let Some(ref mut cipher) = self.cipher {
+            cipher.decrypt(&mut buf[self.decrypted..]);
+            self.decrypted = buf.len();
+        }
+        let (len, off) = match varint::read_slice(&buf[..]) {
+            Some(c) => c,
+            None if buf.len() >= varint::MAX_VARINT_LEN =>
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad packet length")),
+            None => return Ok(None),
+        };
+        // Refuse it before buffering it all
+        if len < 0 || len as usize > self.max_frame_len {
+            return Err(too_big("Packet", len as i64, self.max_frame_len));
+        }
+        let len = len as usize;
+        if buf.len() < len + off {
+            // Only a little at a time, so a length prefix alone can't hold megabytes
+            buf.reserve((len + off - buf.len()).min(RESERVE_CHUNK));
             return Ok(None)
         }
-        let mut data = buf.split_to(len as usize + off).split_off(off).freeze();
+        self.decrypted = self.decrypted.saturating_sub(len + off);
+        let mut data = buf.split_to(len + off).split_off(off).freeze();
         let data = if self.compression.is_some() {
-            let (compressed_length,offset) = varint::read_slice(&data[..]).ok_or(io::ErrorKind::Other)?;
-            if compressed_length == 0 {
-                data.split_off(1)
+            let (uncompressed_length, offset) = varint::read_slice(&data[..])
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad uncompressed length"))?;
+            if uncompressed_length == 0 {
+                data.split_off(offset)
+            } else if uncompressed_length < 0 || uncompressed_length as usize > self.max_uncompressed_len {
+                return Err(too_big("Compressed packet", uncompressed_length as i64, self.max_uncompressed_len));
             } else {
                 use std::io::Read;
-                let mut r = ZlibDecoder::new(&data[offset..]);
-                let mut out = Vec::new();
-                r.read_to_end(&mut out).map_err(|c| {println!("Decompression error: {} ({:X?})", c, data); c})?;
+                let uncompressed_length = uncompressed_length as usize;
+                // One more than it should be, to know if there's more than it said
+                let mut r = ZlibDecoder::new(&data[offset..]).take(uncompressed_length as u64 + 1);
+                // Trusting the length would let a few bytes allocate megabytes, so grow as it inflates
+                let mut out = Vec::with_capacity(uncompressed_length.min(data.len() * 4));
+                r.read_to_end(&mut out)?;
+                if out.len() > uncompressed_length {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                        "Compressed packet is longer than the {} bytes it said", uncompressed_length)));
+                } else if out.len() < uncompressed_length {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                        "Compressed packet is {} bytes, not the {} it said", out.len(), uncompressed_length)));
+                }
                 bytes::Bytes::from(out)
             }
         } else {
@@ -54,8 +195,7 @@ impl Decoder for Codec {
     }
 }
 
-impl Encoder for Codec {
-    type Item = RawPacket;
+impl Encoder<RawPacket> for Codec {
     type Error = io::Error;
     fn encode(&mut self, item: RawPacket, buf: &mut BytesMut) -> Result<(), io::Error> {
         let compression = self.compression;
@@ -90,7 +230,11 @@ impl Encoder for Codec {
                 }
             }
         };
+        let start = buf.len();
         write_packet(item.0, buf);
+        if let Some(ref mut cipher) = self.cipher {
+            cipher.encrypt(&mut buf[start..]);
+        }
         Ok(())
     }
 }
@@ -105,6 +249,24 @@ impl RawPacket {
     pub fn into_clientbound<P: Protocol>(&self) -> Result<P::Clientbound, de::Error> {
         de::from_slice(&self.0)
     }
+    /// Reads the packet as something that borrows from it, so looking at it doesn't allocate.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use mcproto_min_async::codec::RawPacket;
+    /// #[derive(Deserialize)]
+    /// enum Serverbound<'a> {
+    ///     Chat { message: &'a str, signature: &'a [u8] },
+    /// }
+    /// let packet = RawPacket(bytes::Bytes::from_static(b"\x00\x02hi\x01\xff"));
+    /// let Serverbound::Chat { message, signature } = packet.parse().unwrap();
+    /// assert_eq!(message, "hi");
+    /// assert_eq!(message.as_ptr(), packet[2..].as_ptr());
+    /// assert_eq!(signature.as_ptr(), packet[5..].as_ptr());
+    /// ```
+    pub fn parse<'a, T: Deserialize<'a>>(&'a self) -> Result<T, de::Error> {
+        de::from_slice(&self.0)
+    }
 }
 
 impl std::ops::Deref for RawPacket {
@@ -1,7 +1,6 @@
 ///! An implementation of the minecraft protocol as a `serde` deserializer.
 
 use std::io::Cursor;
-use std::io::Read;
 use std::io;
 
 use std::fmt;
@@ -11,54 +10,220 @@ use byteorder::{ReadBytesExt, BE};
 
 use crate::varint;
 
+/// Reads a whole packet. Anything left over afterwards is an error, since it usually means the
+/// packet was read as the wrong thing.
 pub fn from_slice<'de, T: de::Deserialize<'de>>(bytes: &'de [u8]) -> Result<T,Error> {
-    let mut de = Deserializer::from_slice(bytes);
-    T::deserialize(&mut de)
+    from_slice_with_limits(bytes, Limits::default())
+}
+
+/// Like [`from_slice`], with other limits on what's in the packet.
+pub fn from_slice_with_limits<'de, T: de::Deserialize<'de>>(bytes: &'de [u8], limits: Limits) -> Result<T,Error> {
+    let mut de = Deserializer::with_limits(bytes, limits);
+    let value = T::deserialize(&mut de).and_then(|value| {
+        match bytes.len() - de.data.position() as usize {
+            0 => Ok(value),
+            n => Err(Error::new(ErrorKind::TrailingBytes(n))),
+        }
+    });
+    value.map_err(|mut e| {
+        if e.offset.is_none() {
+            e.offset = Some(de.data.position() as usize);
+        }
+        if e.expected.is_none() {
+            e.expected = Some(std::any::type_name::<T>());
+        }
+        e.packet_id = de.packet_id;
+        e
+    })
+}
+
+/// The most characters the protocol allows in a string.
+pub const MAX_STRING_LEN: usize = 32767;
+
+/// How long things in packets can be. Lengths are also checked against what's left of the packet
+/// before anything is allocated for them.
+///
+/// ```
+/// use mcproto_min_async::de::{self, Limits};
+/// let packet = [&[0x05][..], b"hello"].concat();
+/// assert_eq!(de::from_slice::<String>(&packet).unwrap(), "hello");
+/// assert!(de::from_slice_with_limits::<String>(&packet, Limits { max_string_len: 4 }).is_err());
+/// // 100 elements would be fine, but there aren't that many bytes left
+/// assert!(matches!(de::from_slice::<Vec<u8>>(&[0x64, 0x00]).unwrap_err().kind, de::ErrorKind::Eof));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// In UTF-16 code units, like Java counts them. Defaults to [`MAX_STRING_LEN`].
+    pub max_string_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_string_len: MAX_STRING_LEN }
+    }
 }
 
 pub struct Deserializer<'de> {
-    data: Cursor<&'de [u8]>
+    data: Cursor<&'de [u8]>,
+    limits: Limits,
+    /// The first enum variant read, which for packets is the ID
+    packet_id: Option<i32>,
+}
+
+/// What went wrong reading a packet.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The packet ended in the middle of something
+    Eof,
+    /// A value that isn't allowed, like a bool that's 2 or a string that isn't UTF-8
+    Invalid(String),
+    /// The packet was read, but this many bytes of it were left
+    TrailingBytes(usize),
+    /// Something the protocol has no way to represent, like a map
+    Unsupported(&'static str),
+    /// From a `Deserialize` implementation, like an unknown packet ID
+    Custom(String),
 }
 
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Eof => write!(f, "packet ended early"),
+            ErrorKind::Invalid(what) => write!(f, "invalid {}", what),
+            ErrorKind::TrailingBytes(n) => write!(f, "{} bytes left over", n),
+            ErrorKind::Unsupported(what) => write!(f, "{} can't be read from packets", what),
+            ErrorKind::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// A packet that couldn't be read, and where it went wrong.
+///
+/// ```
+/// use mcproto_min_async::{de, protocol::handshake::Serverbound};
+/// // A handshake whose host says it's 9 bytes long, but only has 4
+/// let err = de::from_slice::<Serverbound>(&[0x00, 0xfa, 0x05, 0x09, b'l', b'o', b'c', b'a']).unwrap_err();
+/// assert!(matches!(err.kind, de::ErrorKind::Eof));
+/// assert_eq!(err.path, ["host"]);
+/// assert_eq!(err.expected, Some("alloc::string::String"));
+/// assert_eq!(err.offset, Some(3));
+/// assert_eq!(err.packet_id, Some(0));
+/// assert_eq!(err.to_string(), "packet ended early reading alloc::string::String at host (byte 3 of packet 0x00)");
+///
+/// // A status request with something after it
+/// let err = de::from_slice::<Serverbound>(&[0x00, 0xfa, 0x05, 0x00, 0x63, 0xdd, 0x01, 0xff]).unwrap_err();
+/// assert!(matches!(err.kind, de::ErrorKind::TrailingBytes(1)));
+/// ```
 #[derive(Debug)]
-pub enum Error {
-    Any,
-    PacketLength,
-    Other,
-    Custom(String)
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Field names down to the one that failed, outermost first. Elements of sequences and
+    /// tuples are their index.
+    pub path: Vec<String>,
+    /// The type that was being read
+    pub expected: Option<&'static str>,
+    /// Where in the packet the field that failed starts
+    pub offset: Option<usize>,
+    /// The packet ID, if it got that far
+    pub packet_id: Option<i32>,
 }
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Error { kind, path: Vec::new(), expected: None, offset: None, packet_id: None }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.kind)?;
+        if let Some(expected) = self.expected {
+            write!(f, " reading {}", expected)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path.join("."))?;
+        }
+        match (self.offset, self.packet_id) {
+            (Some(offset), Some(id)) => write!(f, " (byte {} of packet 0x{:02X})", offset, id),
+            (Some(offset), None) => write!(f, " (byte {})", offset),
+            (None, Some(id)) => write!(f, " (packet 0x{:02X})", id),
+            (None, None) => Ok(()),
+        }
     }
 }
 impl std::error::Error for Error {}
 impl serde::de::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        Error::Custom(msg.to_string())
+        Error::new(ErrorKind::Custom(msg.to_string()))
     }
 }
 
 impl From<io::Error> for Error {
-    fn from(_e: io::Error) -> Error {
-        Error::PacketLength
+    fn from(e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::new(ErrorKind::Eof),
+            // Bad varints
+            _ => Error::new(ErrorKind::Invalid(e.to_string())),
+        }
     }
 }
 impl From<io::ErrorKind> for Error {
-    fn from(_e: io::ErrorKind) -> Error {
-        Error::PacketLength
+    fn from(e: io::ErrorKind) -> Error {
+        io::Error::from(e).into()
     }
 }
 
 impl From<Error> for io::Error {
     fn from(e: Error) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, e)
+        io::Error::new(io::ErrorKind::InvalidData, e)
     }
 }
 
 impl<'de> Deserializer<'de> {
     pub fn from_slice(input: &'de [u8]) -> Deserializer<'de> {
-        Deserializer { data: Cursor::new(input) }
+        Self::with_limits(input, Limits::default())
+    }
+    pub fn with_limits(input: &'de [u8], limits: Limits) -> Deserializer<'de> {
+        Deserializer { data: Cursor::new(input), limits, packet_id: None }
+    }
+    /// Checks a length read from the packet, which can't be more than what's left of it.
+    fn check_len(&self, len: i64) -> Result<usize, Error> {
+        let remaining = self.data.get_ref().len() as u64 - self.data.position();
+        if len < 0 {
+            Err(Error::new(ErrorKind::Invalid(format!("length {}", len))))
+        } else if len as u64 > remaining {
+            Err(Error::new(ErrorKind::Eof))
+        } else {
+            Ok(len as usize)
+        }
+    }
+    /// Takes the next `len` bytes as they are, without copying them.
+    fn take(&mut self, len: usize) -> &'de [u8] {
+        let data = *self.data.get_ref();
+        let start = self.data.position() as usize;
+        self.data.set_position((start + len) as u64);
+        &data[start..start + len]
+    }
+    fn read_buf(&mut self) -> Result<&'de [u8], Error> {
+        let len = varint::read(&mut self.data)?;
+        let len = self.check_len(len as i64)?;
+        Ok(self.take(len))
+    }
+    fn read_string(&mut self) -> Result<&'de str, Error> {
+        let max = self.limits.max_string_len;
+        let too_long = |len| Error::new(ErrorKind::Invalid(format!("string of {} over the limit of {} characters", len, max)));
+        let len = varint::read(&mut self.data)?;
+        // Java's limit is on UTF-16, which takes at most 3 bytes of UTF-8 per code unit
+        if len as i64 > max as i64 * 3 {
+            return Err(too_long(format!("{} bytes", len)));
+        }
+        let len = self.check_len(len as i64)?;
+        let s = std::str::from_utf8(self.take(len)).map_err(|_| Error::new(ErrorKind::Invalid("UTF-8".to_string())))?;
+        let units = s.encode_utf16().count();
+        if units > max {
+            return Err(too_long(format!("{} characters", units)));
+        }
+        Ok(s)
     }
 }
 macro_rules! build_de {
@@ -74,12 +239,12 @@ macro_rules! build_de {
 
 build_de! {
     self, visitor:
-    deserialize_any => Err(Error::Any),
+    deserialize_any => Err(Error::new(ErrorKind::Unsupported("any"))),
     deserialize_bool => visitor.visit_bool({
         match self.data.read_u8()? {
             0 => false,
             1 => true,
-            _ => Err(Error::Other)?
+            b => Err(Error::new(ErrorKind::Invalid(format!("bool {}", b))))?
         }
     }),
     deserialize_i8 => visitor.visit_i8(self.data.read_i8()?),
@@ -94,43 +259,29 @@ build_de! {
     deserialize_u128 => visitor.visit_u128(self.data.read_u128::<BE>()?),
     deserialize_f32 => visitor.visit_f32(self.data.read_f32::<BE>()?),
     deserialize_f64 => visitor.visit_f64(self.data.read_f64::<BE>()?),
-    // TODO
-    deserialize_str => Err(Error::Any),
-    deserialize_string => visitor.visit_string({
-        let len = varint::read(&mut self.data)?;
-        // TODO: take a slice of the string as is instead of allocating
-        let mut buf = vec![0;len as usize];
-        self.data.read_exact(&mut buf);
-
-        String::from_utf8_lossy(&*buf).to_string()
-    }),
-    deserialize_char => Err(Error::Any),
-    deserialize_bytes => {
-        let len = varint::read(&mut self.data)?;
-        let mut buf = vec![0;len as usize];
-        self.data.read_exact(&mut buf);
-        visitor.visit_bytes(&buf)
-    },
-    deserialize_byte_buf => {
-        let len = varint::read(&mut self.data)?;
-        let mut buf = vec![0;len as usize];
-        self.data.read_exact(&mut buf);
-        visitor.visit_byte_buf(buf)
-    },
+    // Borrowed from the packet, so `&str` and `&[u8]` fields don't allocate, and owned ones only
+    // copy once
+    deserialize_str => visitor.visit_borrowed_str(self.read_string()?),
+    deserialize_string => visitor.visit_borrowed_str(self.read_string()?),
+    deserialize_char => Err(Error::new(ErrorKind::Unsupported("char"))),
+    deserialize_bytes => visitor.visit_borrowed_bytes(self.read_buf()?),
+    deserialize_byte_buf => visitor.visit_borrowed_bytes(self.read_buf()?),
     deserialize_option => {
         match self.data.read_u8()? {
             0 => visitor.visit_none(),
             1 => visitor.visit_some(self),
-            _ => Err(Error::Other)?
+            b => Err(Error::new(ErrorKind::Invalid(format!("option marker {}", b))))?
         }
     },
-    deserialize_unit => Err(Error::Any),
-    deserialize_map => Err(Error::Any),
-    deserialize_identifier => Err(Error::Any),
-    deserialize_ignored_any => Err(Error::Any),
+    deserialize_unit => Err(Error::new(ErrorKind::Unsupported("unit"))),
+    deserialize_map => Err(Error::new(ErrorKind::Unsupported("map"))),
+    deserialize_identifier => Err(Error::new(ErrorKind::Unsupported("identifier"))),
+    deserialize_ignored_any => Err(Error::new(ErrorKind::Unsupported("ignored_any"))),
     deserialize_seq => {
+        // Every element takes at least a byte
         let len = varint::read(&mut self.data)?;
-        self.deserialize_tuple(len as usize, visitor)
+        let len = self.check_len(len as i64)?;
+        self.deserialize_tuple(len, visitor)
     };
     deserialize_newtype_struct(name: &'static str) => {
         match name {
@@ -138,26 +289,32 @@ build_de! {
                 let num = varint::read(&mut self.data)?;
                 visitor.visit_i32(num)
             },
+            "*VARLONG" => {
+                let num = varint::read_long(&mut self.data)?;
+                visitor.visit_i64(num)
+            },
             "*REST" => {
-                let position = self.data.position() as usize;
-                visitor.visit_bytes(&self.data.get_ref()[position..])
+                let len = self.data.get_ref().len() - self.data.position() as usize;
+                visitor.visit_borrowed_bytes(self.take(len))
             },
             "*INTPREFIXED" => {
-                let len = self.data.read_i32::<BE>()? as usize;
-                visitor.visit_seq(Consequent { de: &mut self, len })
+                let len = self.data.read_i32::<BE>()?;
+                let len = self.check_len(len as i64)?;
+                visitor.visit_seq(Consequent::new(self, len))
             },
             "*SHORTPREFIXED" => {
-                let len = self.data.read_i16::<BE>()? as usize;
-                visitor.visit_seq(Consequent { de: &mut self, len })
+                let len = self.data.read_i16::<BE>()?;
+                let len = self.check_len(len as i64)?;
+                visitor.visit_seq(Consequent::new(self, len))
             },
             _ => visitor.visit_newtype_struct(self)
         }
     },
     deserialize_unit_struct(name: &'static str) => visitor.visit_unit(),
-    deserialize_tuple_struct(name: &'static str, len: usize) => Err(Error::Any),
-    deserialize_tuple(len: usize) => visitor.visit_seq(Consequent { de: &mut self, len }),
+    deserialize_tuple_struct(name: &'static str, len: usize) => Err(Error::new(ErrorKind::Unsupported("tuple struct"))),
+    deserialize_tuple(len: usize) => visitor.visit_seq(Consequent::new(self, len)),
     deserialize_struct(name: &'static str, fields: &'static [&'static str]) => {
-        self.deserialize_tuple(fields.len(), visitor)
+        visitor.visit_seq(Consequent::fields(self, fields))
     },
     deserialize_enum(name: &'static str, variants: &'static [&'static str]) => {
         visitor.visit_enum(Enum { de: &mut self })
@@ -166,7 +323,19 @@ build_de! {
 
 struct Consequent<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
-    len: usize
+    len: usize,
+    /// For naming them in errors, if they're a struct's
+    fields: Option<&'static [&'static str]>,
+    index: usize,
+}
+
+impl<'a, 'de> Consequent<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, len: usize) -> Self {
+        Consequent { de, len, fields: None, index: 0 }
+    }
+    fn fields(de: &'a mut Deserializer<'de>, fields: &'static [&'static str]) -> Self {
+        Consequent { de, len: fields.len(), fields: Some(fields), index: 0 }
+    }
 }
 
 impl<'a,'de> SeqAccess<'de> for Consequent<'a, 'de> {
@@ -174,7 +343,21 @@ impl<'a,'de> SeqAccess<'de> for Consequent<'a, 'de> {
     fn next_element_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>,Self::Error> {
         if self.len == 0 { return Ok(None) }
         self.len -= 1;
-        let value = serde::de::DeserializeSeed::deserialize(seed, &mut *self.de)?;
+        let start = self.de.data.position() as usize;
+        let index = self.index;
+        self.index += 1;
+        let value = serde::de::DeserializeSeed::deserialize(seed, &mut *self.de).map_err(|mut e| {
+            // The innermost field to fail says where and what, and each one out adds its name
+            let name = self.fields.and_then(|f| f.get(index));
+            e.path.insert(0, name.map_or_else(|| index.to_string(), |n| n.to_string()));
+            if e.offset.is_none() {
+                e.offset = Some(start);
+            }
+            if e.expected.is_none() {
+                e.expected = Some(std::any::type_name::<K::Value>());
+            }
+            e
+        })?;
         Ok(Some(value))
     }
     fn size_hint(&self) -> Option<usize> {
@@ -192,6 +375,9 @@ impl<'a,'de> de::EnumAccess<'de> for Enum<'a, 'de> {
     fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> {
         use serde::de::IntoDeserializer;
         let c = varint::read(&mut self.de.data)?;
+        if self.de.packet_id.is_none() {
+            self.de.packet_id = Some(c);
+        }
         let val: Result<_,Self::Error> = seed.deserialize((c as u32).into_deserializer());
         Ok((val?, self))
     }
@@ -210,7 +396,6 @@ impl<'a,'de> de::VariantAccess<'de> for Enum<'a,'de> {
         self.de.deserialize_tuple(len, visitor)
     }
     fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
-        use serde::de::Deserializer;
-        self.de.deserialize_tuple(fields.len(), visitor)
+        visitor.visit_seq(Consequent::fields(self.de, fields))
     }
 }
@@ -1,6 +1,12 @@
 pub mod server;
+pub mod client;
 pub mod codec;
 pub mod varint;
 pub mod ser;
 pub mod de;
 pub mod protocol;
+pub mod nbt;
+pub mod auth;
+pub mod proxy;
+pub mod proxy_protocol;
+pub mod text;
@@ -0,0 +1,255 @@
+//! Reading NBT through `serde`.
+
+use std::borrow::Cow;
+use std::io::Read;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use byteorder::{ReadBytesExt, BE};
+
+use super::*;
+
+/// Reads uncompressed NBT. See [`from_reader`](super::from_reader) for compressed data.
+pub struct Deserializer<R> {
+    r: R,
+}
+
+impl<R: Read> Deserializer<R> {
+    pub fn new(r: R) -> Self {
+        Deserializer { r }
+    }
+
+    /// Reads the root compound and its name.
+    pub fn root<T: DeserializeOwned>(mut self) -> Result<(String, T), Error> {
+        let id = self.r.read_u8()?;
+        if id != COMPOUND {
+            return Err(Error::NotCompound(id));
+        }
+        let name = read_string(&mut self.r)?;
+        let value = T::deserialize(Value { r: &mut self.r, id, depth: 0 })?;
+        Ok((name, value))
+    }
+
+    /// Reads a root tag the way the protocol has sent them since 1.20.2: any type, and no name.
+    pub fn network<T: DeserializeOwned>(mut self) -> Result<T, Error> {
+        let id = self.r.read_u8()?;
+        T::deserialize(Value { r: &mut self.r, id, depth: 0 })
+    }
+}
+
+pub(crate) fn read_string(r: &mut impl Read) -> Result<String, Error> {
+    let len = r.read_u16::<BE>()? as usize;
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf)?;
+    // Java writes modified UTF-8, which only differs from the real thing for nulls and
+    // characters outside the BMP
+    match String::from_utf8(buf) {
+        Ok(s) => Ok(s),
+        Err(e) => match cesu8::from_java_cesu8(e.as_bytes()) {
+            Ok(Cow::Owned(s)) => Ok(s),
+            Ok(Cow::Borrowed(s)) => Ok(s.to_string()),
+            Err(_) => Err(Error::InvalidString),
+        },
+    }
+}
+
+/// How deeply lists and compounds can nest, which is vanilla's limit too. Reading recurses, so
+/// without one a few hundred kilobytes of nested lists would overflow the stack.
+pub const MAX_DEPTH: usize = 512;
+
+fn read_len(r: &mut impl Read) -> Result<usize, Error> {
+    match r.read_i32::<BE>()? {
+        len if len < 0 => Err(Error::NegativeLength(len)),
+        len => Ok(len as usize),
+    }
+}
+
+/// A single tag's payload, whose type has already been read.
+struct Value<'a, R> {
+    r: &'a mut R,
+    id: u8,
+    /// How many lists and compounds it's inside of
+    depth: usize,
+}
+
+impl<'de, 'a, R: Read> de::Deserializer<'de> for Value<'a, R> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            BYTE => visitor.visit_i8(self.r.read_i8()?),
+            SHORT => visitor.visit_i16(self.r.read_i16::<BE>()?),
+            INT => visitor.visit_i32(self.r.read_i32::<BE>()?),
+            LONG => visitor.visit_i64(self.r.read_i64::<BE>()?),
+            FLOAT => visitor.visit_f32(self.r.read_f32::<BE>()?),
+            DOUBLE => visitor.visit_f64(self.r.read_f64::<BE>()?),
+            STRING => visitor.visit_string(read_string(self.r)?),
+            BYTE_ARRAY => {
+                let len = read_len(self.r)?;
+                visitor.visit_seq(Elements { r: self.r, id: BYTE, len, depth: self.depth })
+            },
+            INT_ARRAY => {
+                let len = read_len(self.r)?;
+                visitor.visit_seq(Elements { r: self.r, id: INT, len, depth: self.depth })
+            },
+            LONG_ARRAY => {
+                let len = read_len(self.r)?;
+                visitor.visit_seq(Elements { r: self.r, id: LONG, len, depth: self.depth })
+            },
+            LIST | COMPOUND if self.depth >= MAX_DEPTH => Err(Error::TooDeep),
+            LIST => {
+                let id = self.r.read_u8()?;
+                let len = read_len(self.r)?;
+                if id > LONG_ARRAY || (id == END && len > 0) {
+                    return Err(Error::UnknownTag(id));
+                }
+                visitor.visit_seq(Elements { r: self.r, id, len, depth: self.depth + 1 })
+            },
+            COMPOUND => visitor.visit_map(Fields { r: self.r, id: END, depth: self.depth + 1 }),
+            id => Err(Error::UnknownTag(id)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            BYTE => visitor.visit_bool(self.r.read_i8()? != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // Minecraft doesn't have unsigned types, but some fields are meant to be read as such
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            BYTE => visitor.visit_u8(self.r.read_u8()?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            SHORT => visitor.visit_u16(self.r.read_u16::<BE>()?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            INT => visitor.visit_u32(self.r.read_u32::<BE>()?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            LONG => visitor.visit_u64(self.r.read_u64::<BE>()?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.id {
+            BYTE_ARRAY => {
+                let len = read_len(self.r)?;
+                let mut buf = Vec::new();
+                self.r.take(len as u64).read_to_end(&mut buf)?;
+                if buf.len() != len {
+                    return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+                }
+                visitor.visit_byte_buf(buf)
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    /// A tag that's present is always `Some`; missing struct fields become `None` by themselves.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        match (name, self.id) {
+            ("*NBT_TAG", id) if id == END || id > LONG_ARRAY => Err(Error::UnknownTag(id)),
+            // Tag's variants are numbered by tag ID
+            ("*NBT_TAG", _) => visitor.visit_enum(TagVariant(self)),
+            (_, STRING) => visitor.visit_enum(read_string(self.r)?.into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string unit unit_struct seq tuple tuple_struct
+        map struct identifier ignored_any
+    }
+}
+
+struct Elements<'a, R> {
+    r: &'a mut R,
+    id: u8,
+    len: usize,
+    depth: usize,
+}
+
+impl<'de, 'a, R: Read> SeqAccess<'de> for Elements<'a, R> {
+    type Error = Error;
+    fn next_element_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        if self.len == 0 { return Ok(None) }
+        self.len -= 1;
+        seed.deserialize(Value { r: &mut *self.r, id: self.id, depth: self.depth }).map(Some)
+    }
+    fn size_hint(&self) -> Option<usize> {
+        // Don't let a corrupt length preallocate gigabytes
+        Some(self.len.min(4096))
+    }
+}
+
+struct Fields<'a, R> {
+    r: &'a mut R,
+    /// The type of the field whose name was just read
+    id: u8,
+    depth: usize,
+}
+
+impl<'de, 'a, R: Read> MapAccess<'de> for Fields<'a, R> {
+    type Error = Error;
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        self.id = self.r.read_u8()?;
+        if self.id == END {
+            return Ok(None);
+        }
+        let name = read_string(self.r)?;
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(Value { r: &mut *self.r, id: self.id, depth: self.depth })
+    }
+}
+
+struct TagVariant<'a, R>(Value<'a, R>);
+
+impl<'de, 'a, R: Read> de::EnumAccess<'de> for TagVariant<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let id: Result<_, Error> = seed.deserialize((self.0.id as u32).into_deserializer());
+        Ok((id?, self))
+    }
+}
+
+impl<'de, 'a, R: Read> de::VariantAccess<'de> for TagVariant<'a, R> {
+    type Error = Error;
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(Error::Unsupported("unit variant"))
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.0)
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error::Unsupported("tuple variant"))
+    }
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Error> {
+        Err(Error::Unsupported("struct variant"))
+    }
+}
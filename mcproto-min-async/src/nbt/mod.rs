@@ -0,0 +1,248 @@
+//! Minecraft's Named Binary Tag format, as a `serde` data format.
+//!
+//! Compounds map to structs and maps, lists to sequences, and the primitives to their Rust
+//! counterparts. Sequences are always written as lists; wrap them in [`ByteArray`], [`IntArray`]
+//! or [`LongArray`] to write the array tags instead. For data without a fixed shape, use [`Tag`].
+//!
+//! Files like `level.dat` are gzipped, region chunks are usually zlib'd, and packets aren't
+//! compressed at all. Reading detects which one it's given.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+use flate2::{read::GzDecoder, read::ZlibDecoder, write::GzEncoder, write::ZlibEncoder};
+
+pub mod de;
+pub mod ser;
+mod tag;
+
+pub use tag::Tag;
+
+pub(crate) const END: u8 = 0;
+pub(crate) const BYTE: u8 = 1;
+pub(crate) const SHORT: u8 = 2;
+pub(crate) const INT: u8 = 3;
+pub(crate) const LONG: u8 = 4;
+pub(crate) const FLOAT: u8 = 5;
+pub(crate) const DOUBLE: u8 = 6;
+pub(crate) const BYTE_ARRAY: u8 = 7;
+pub(crate) const STRING: u8 = 8;
+pub(crate) const LIST: u8 = 9;
+pub(crate) const COMPOUND: u8 = 10;
+pub(crate) const INT_ARRAY: u8 = 11;
+pub(crate) const LONG_ARRAY: u8 = 12;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Custom(String),
+    /// A tag ID outside of 0-12
+    UnknownTag(u8),
+    /// The root tag wasn't a compound
+    NotCompound(u8),
+    /// A list element didn't have the same type as the rest of the list
+    MixedList { expected: u8, found: u8 },
+    /// A negative length or count
+    NegativeLength(i32),
+    /// A string longer than the 65535 bytes its length prefix can hold
+    StringTooLong(usize),
+    /// A string that isn't valid (modified) UTF-8
+    InvalidString,
+    /// A Rust type that NBT has no representation for
+    Unsupported(&'static str),
+    /// Lists and compounds nested more than [`de::MAX_DEPTH`] deep
+    TooDeep,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Custom(msg) => write!(f, "{}", msg),
+            Error::UnknownTag(id) => write!(f, "unknown tag type {}", id),
+            Error::NotCompound(id) => write!(f, "root tag is type {}, not a compound", id),
+            Error::MixedList { expected, found } =>
+                write!(f, "list of type {} has an element of type {}", expected, found),
+            Error::NegativeLength(len) => write!(f, "negative length {}", len),
+            Error::StringTooLong(len) => write!(f, "string of {} bytes is too long", len),
+            Error::InvalidString => write!(f, "string is not valid modified UTF-8"),
+            Error::Unsupported(what) => write!(f, "{} can't be represented in NBT", what),
+            Error::TooDeep => write!(f, "tags are nested more than {} deep", de::MAX_DEPTH),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+impl Compression {
+    /// Guesses the compression from the first bytes of the data. Uncompressed NBT starts with a
+    /// compound tag ID, which neither header can be mistaken for.
+    pub fn detect(header: &[u8]) -> Compression {
+        match header {
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [0x78, ..] => Compression::Zlib,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Reads a value from NBT in any compression, discarding the root tag's name.
+pub fn from_reader<T: DeserializeOwned>(r: impl Read) -> Result<T, Error> {
+    from_reader_named(r).map(|(_, value)| value)
+}
+
+/// Reads a value from NBT in any compression, along with the root tag's name.
+pub fn from_reader_named<T: DeserializeOwned>(r: impl Read) -> Result<(String, T), Error> {
+    let mut r = BufReader::new(r);
+    match Compression::detect(r.fill_buf()?) {
+        Compression::Gzip => de::Deserializer::new(BufReader::new(GzDecoder::new(r))).root(),
+        Compression::Zlib => de::Deserializer::new(BufReader::new(ZlibDecoder::new(r))).root(),
+        Compression::None => de::Deserializer::new(r).root(),
+    }
+}
+
+/// Reads a value from NBT in any compression.
+///
+/// Strings are modified UTF-8, as Java writes them: nulls take two bytes, and characters outside
+/// the BMP are surrogate pairs.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use mcproto_min_async::nbt::{self, Error};
+/// let data = [
+///     10, 0, 0,
+///     8, 0, 1, b's', 0, 9, b'a', 0xc0, 0x80, 0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80,
+///     0,
+/// ];
+/// let read: BTreeMap<String, String> = nbt::from_slice(&data).unwrap();
+/// assert_eq!(read["s"], "a\0\u{1f600}");
+/// assert_eq!(nbt::to_vec("", &read, nbt::Compression::None).unwrap(), data);
+///
+/// let negative = [10, 0, 0, 11, 0, 1, b'i', 0xff, 0xff, 0xff, 0xff, 0];
+/// assert!(matches!(nbt::from_slice::<nbt::Tag>(&negative), Err(Error::NegativeLength(-1))));
+/// let unknown = [10, 0, 0, 13, 0, 1, b'x', 0];
+/// assert!(matches!(nbt::from_slice::<nbt::Tag>(&unknown), Err(Error::UnknownTag(13))));
+/// let not_compound = [8, 0, 0, 0, 0];
+/// assert!(matches!(nbt::from_slice::<nbt::Tag>(&not_compound), Err(Error::NotCompound(8))));
+///
+/// // A list of a list of a list...
+/// let mut deep = vec![10, 0, 0, 9, 0, 0];
+/// for _ in 0..100_000 {
+///     deep.extend_from_slice(&[9, 0, 0, 0, 1]);
+/// }
+/// assert!(matches!(nbt::from_slice::<nbt::Tag>(&deep), Err(Error::TooDeep)));
+/// ```
+pub fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    from_reader(data)
+}
+
+/// Reads uncompressed NBT with a nameless root of any type, as packets have used since 1.20.2.
+pub fn from_network_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    de::Deserializer::new(data).network()
+}
+
+/// Writes a value as a named root compound.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use mcproto_min_async::nbt::{self, Compression};
+/// let mut level = BTreeMap::new();
+/// level.insert("LevelName".to_string(), "world".to_string());
+/// for compression in [Compression::None, Compression::Gzip, Compression::Zlib] {
+///     let data = nbt::to_vec("Data", &level, compression).unwrap();
+///     assert_eq!(Compression::detect(&data), compression);
+///     let (name, read): (String, BTreeMap<String, String>) = nbt::from_reader_named(&data[..]).unwrap();
+///     assert_eq!((name.as_str(), &read), ("Data", &level));
+/// }
+/// ```
+pub fn to_writer<T: Serialize>(w: impl Write, name: &str, value: &T, compression: Compression) -> Result<(), Error> {
+    match compression {
+        Compression::None => ser::Serializer::new(w).root(name, value),
+        Compression::Gzip => {
+            let mut w = GzEncoder::new(w, flate2::Compression::default());
+            ser::Serializer::new(&mut w).root(name, value)?;
+            w.try_finish()?;
+            Ok(())
+        },
+        Compression::Zlib => {
+            let mut w = ZlibEncoder::new(w, flate2::Compression::default());
+            ser::Serializer::new(&mut w).root(name, value)?;
+            w.try_finish()?;
+            Ok(())
+        },
+    }
+}
+
+pub fn to_vec<T: Serialize>(name: &str, value: &T, compression: Compression) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    to_writer(&mut out, name, value, compression)?;
+    Ok(out)
+}
+
+macro_rules! array_wrapper {
+    ($(#[$doc:meta])* $name:ident($t:ty) = $special:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct $name(pub Vec<$t>);
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+                ser.serialize_newtype_struct($special, &self.0)
+            }
+        }
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+                Vec::deserialize(de).map($name)
+            }
+        }
+        impl std::ops::Deref for $name {
+            type Target = Vec<$t>;
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+    }
+}
+
+array_wrapper! {
+    /// Serializes as a `TAG_Byte_Array` instead of a list of bytes.
+    ByteArray(i8) = "*NBT_BYTEARRAY"
+}
+array_wrapper! {
+    /// Serializes as a `TAG_Int_Array` instead of a list of ints.
+    IntArray(i32) = "*NBT_INTARRAY"
+}
+array_wrapper! {
+    /// Serializes as a `TAG_Long_Array` instead of a list of longs.
+    LongArray(i64) = "*NBT_LONGARRAY"
+}
@@ -0,0 +1,367 @@
+//! Writing NBT through `serde`.
+
+use std::io::Write;
+
+use serde::ser::{self, Impossible};
+use byteorder::{WriteBytesExt, BE};
+
+use super::*;
+
+/// Writes uncompressed NBT. See [`to_writer`](super::to_writer) for compressed data.
+pub struct Serializer<W> {
+    w: W,
+}
+
+impl<W: Write> Serializer<W> {
+    pub fn new(w: W) -> Self {
+        Serializer { w }
+    }
+
+    /// Writes a value, which must serialize as a compound, as the named root tag.
+    pub fn root<T: Serialize + ?Sized>(mut self, name: &str, value: &T) -> Result<(), Error> {
+        value.serialize(Value { w: &mut self.w, head: Head::Root(name), array: None })
+    }
+}
+
+pub(crate) fn write_string(w: &mut impl Write, s: &str) -> Result<(), Error> {
+    let data = cesu8::to_java_cesu8(s);
+    if data.len() > u16::MAX as usize {
+        return Err(Error::StringTooLong(data.len()));
+    }
+    w.write_u16::<BE>(data.len() as u16)?;
+    w.write_all(&data)?;
+    Ok(())
+}
+
+fn write_len(w: &mut impl Write, len: usize) -> Result<(), Error> {
+    if len > i32::MAX as usize {
+        return Err(Error::Unsupported("sequence longer than i32::MAX"));
+    }
+    w.write_i32::<BE>(len as i32)?;
+    Ok(())
+}
+
+/// What has to be written ahead of a tag's payload, which depends on where it is.
+enum Head<'a> {
+    /// The root compound, which only compounds can be
+    Root(&'a str),
+    /// A field in a compound: the type and the name
+    Named(&'a str),
+    /// A list element: the first element decides the list's type
+    Element(&'a mut ListState),
+    /// An element of an array tag, which has no header but has to be the right type
+    ArrayElement(u8),
+}
+
+struct ListState {
+    len: usize,
+    id: Option<u8>,
+}
+
+/// Serializes one tag.
+struct Value<'a, W> {
+    w: &'a mut W,
+    head: Head<'a>,
+    /// Set by the array wrappers, as (array type, element type)
+    array: Option<(u8, u8)>,
+}
+
+impl<'a, W: Write> Value<'a, W> {
+    /// Writes whatever comes before a payload of the given type.
+    fn start(self, id: u8) -> Result<&'a mut W, Error> {
+        match self.head {
+            Head::Root(name) => {
+                if id != COMPOUND {
+                    return Err(Error::NotCompound(id));
+                }
+                self.w.write_u8(id)?;
+                write_string(self.w, name)?;
+            },
+            Head::Named(name) => {
+                self.w.write_u8(id)?;
+                write_string(self.w, name)?;
+            },
+            Head::Element(list) => match list.id {
+                None => {
+                    self.w.write_u8(id)?;
+                    write_len(self.w, list.len)?;
+                    list.id = Some(id);
+                },
+                Some(expected) if expected != id => return Err(Error::MixedList { expected, found: id }),
+                Some(_) => {},
+            },
+            Head::ArrayElement(expected) => if expected != id {
+                return Err(Error::MixedList { expected, found: id });
+            },
+        }
+        Ok(self.w)
+    }
+}
+
+impl<'a, W: Write> ser::Serializer for Value<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = List<'a, W>;
+    type SerializeTuple = List<'a, W>;
+    type SerializeTupleStruct = List<'a, W>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Compound<'a, W>;
+    type SerializeStruct = Compound<'a, W>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.serialize_i8(v as i8)
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        Ok(self.start(BYTE)?.write_i8(v)?)
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        Ok(self.start(SHORT)?.write_i16::<BE>(v)?)
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        Ok(self.start(INT)?.write_i32::<BE>(v)?)
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        Ok(self.start(LONG)?.write_i64::<BE>(v)?)
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_i8(v as i8)
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_i16(v as i16)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_i32(v as i32)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        Ok(self.start(FLOAT)?.write_f32::<BE>(v)?)
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        Ok(self.start(DOUBLE)?.write_f64::<BE>(v)?)
+    }
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        write_string(self.start(STRING)?, v)
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        let w = self.start(BYTE_ARRAY)?;
+        write_len(w, v.len())?;
+        w.write_all(v)?;
+        Ok(())
+    }
+    /// `None` writes nothing at all, so optional fields are left out of their compound.
+    fn serialize_none(self) -> Result<(), Error> {
+        match self.head {
+            Head::Named(_) => Ok(()),
+            _ => Err(Error::Unsupported("None outside of a compound")),
+        }
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), Error> {
+        Err(Error::Unsupported("()"))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Err(Error::Unsupported("unit struct"))
+    }
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), Error> {
+        self.serialize_str(variant)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(mut self, name: &'static str, value: &T) -> Result<(), Error> {
+        self.array = match name {
+            "*NBT_BYTEARRAY" => Some((BYTE_ARRAY, BYTE)),
+            "*NBT_INTARRAY" => Some((INT_ARRAY, INT)),
+            "*NBT_LONGARRAY" => Some((LONG_ARRAY, LONG)),
+            _ => None,
+        };
+        value.serialize(self)
+    }
+    /// Externally tagged, as a compound with the variant as its only field.
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<(), Error> {
+        let w = self.start(COMPOUND)?;
+        value.serialize(Value { w: &mut *w, head: Head::Named(variant), array: None })?;
+        w.write_u8(END)?;
+        Ok(())
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<List<'a, W>, Error> {
+        let len = len.ok_or(Error::Unsupported("sequence without a length"))?;
+        match self.array {
+            Some((array, element)) => {
+                let w = self.start(array)?;
+                write_len(w, len)?;
+                Ok(List { w, state: ListState { len, id: Some(element) }, array: true })
+            },
+            None => Ok(List { w: self.start(LIST)?, state: ListState { len, id: None }, array: false }),
+        }
+    }
+    fn serialize_tuple(self, len: usize) -> Result<List<'a, W>, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<List<'a, W>, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Unsupported("tuple variant"))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a, W>, Error> {
+        Ok(Compound { w: self.start(COMPOUND)?, key: None })
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a, W>, Error> {
+        Ok(Compound { w: self.start(COMPOUND)?, key: None })
+    }
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::Unsupported("struct variant"))
+    }
+    fn serialize_i128(self, _v: i128) -> Result<(), Error> {
+        Err(Error::Unsupported("i128"))
+    }
+    fn serialize_u128(self, _v: u128) -> Result<(), Error> {
+        Err(Error::Unsupported("u128"))
+    }
+}
+
+struct List<'a, W> {
+    w: &'a mut W,
+    state: ListState,
+    array: bool,
+}
+
+impl<'a, W: Write> List<'a, W> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let head = match self.state.id {
+            Some(id) if self.array => Head::ArrayElement(id),
+            _ => Head::Element(&mut self.state),
+        };
+        value.serialize(Value { w: &mut *self.w, head, array: None })
+    }
+    fn finish(self) -> Result<(), Error> {
+        // An empty list never had an element to give it a type
+        if self.state.id.is_none() {
+            self.w.write_u8(END)?;
+            self.w.write_i32::<BE>(0)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> ser::SerializeSeq for List<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> { self.finish() }
+}
+impl<'a, W: Write> ser::SerializeTuple for List<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> { self.finish() }
+}
+impl<'a, W: Write> ser::SerializeTupleStruct for List<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> { self.finish() }
+}
+
+struct Compound<'a, W> {
+    w: &'a mut W,
+    key: Option<String>,
+}
+
+impl<'a, W: Write> ser::SerializeStruct for Compound<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        value.serialize(Value { w: &mut *self.w, head: Head::Named(key), array: None })
+    }
+    fn end(self) -> Result<(), Error> {
+        Ok(self.w.write_u8(END)?)
+    }
+}
+
+impl<'a, W: Write> ser::SerializeMap for Compound<'a, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or_else(|| Error::Custom("map value without a key".to_string()))?;
+        value.serialize(Value { w: &mut *self.w, head: Head::Named(&key), array: None })
+    }
+    fn end(self) -> Result<(), Error> {
+        Ok(self.w.write_u8(END)?)
+    }
+}
+
+/// Compound names are strings, so only string-like map keys work.
+struct KeySerializer;
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> { Ok(v.to_string()) }
+    fn serialize_char(self, v: char) -> Result<String, Error> { Ok(v.to_string()) }
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<String, Error> {
+        Ok(variant.to_string())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_i8(self, _v: i8) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_i16(self, _v: i16) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_i32(self, _v: i32) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_i64(self, _v: i64) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_u8(self, _v: u8) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_u16(self, _v: u16) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_u32(self, _v: u32) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_u64(self, _v: u64) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_f32(self, _v: f32) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_f64(self, _v: f64) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_none(self) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_unit(self) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<String, Error> {
+        Err(Error::Unsupported("non-string key"))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error::Unsupported("non-string key"))
+    }
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Unsupported("non-string key"))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> { Err(Error::Unsupported("non-string key")) }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+        Err(Error::Unsupported("non-string key"))
+    }
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::Unsupported("non-string key"))
+    }
+}
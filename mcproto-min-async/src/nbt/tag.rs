@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{self, VariantAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::*;
+
+/// Any NBT value, for data without a fixed shape.
+///
+/// This only deserializes from NBT, since other formats can't say which tag type a value was.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use mcproto_min_async::nbt::{self, Compression, Tag};
+/// let mut fields = BTreeMap::new();
+/// fields.insert("byte".to_string(), Tag::Byte(-1));
+/// fields.insert("short".to_string(), Tag::Short(i16::MIN));
+/// fields.insert("int".to_string(), Tag::Int(i32::MAX));
+/// fields.insert("long".to_string(), Tag::Long(i64::MIN));
+/// fields.insert("float".to_string(), Tag::Float(0.5));
+/// fields.insert("double".to_string(), Tag::Double(-1e300));
+/// fields.insert("bytes".to_string(), Tag::ByteArray(vec![1, -2, 3]));
+/// fields.insert("string".to_string(), Tag::String("héllo".to_string()));
+/// fields.insert("empty".to_string(), Tag::List(vec![]));
+/// fields.insert("nested".to_string(), Tag::List(vec![
+///     Tag::List(vec![Tag::Int(1), Tag::Int(2)]),
+///     Tag::List(vec![]),
+/// ]));
+/// fields.insert("compound".to_string(), Tag::Compound(BTreeMap::new()));
+/// fields.insert("ints".to_string(), Tag::IntArray(vec![i32::MIN, 0]));
+/// fields.insert("longs".to_string(), Tag::LongArray(vec![i64::MAX, 0, -1]));
+/// let tag = Tag::Compound(fields);
+///
+/// let data = nbt::to_vec("root", &tag, Compression::None).unwrap();
+/// let (name, read): (String, Tag) = nbt::from_reader_named(&data[..]).unwrap();
+/// assert_eq!(name, "root");
+/// assert_eq!(read, tag);
+/// assert_eq!(read.get("nested").unwrap().as_list().unwrap()[0].as_list().unwrap().len(), 2);
+///
+/// // Reading and writing back is byte for byte, given fields in order
+/// let data = [
+///     10, 0, 0,
+///     9, 0, 1, b'l', 9, 0, 0, 0, 2,
+///         3, 0, 0, 0, 1, 0, 0, 0, 7,
+///         0, 0, 0, 0, 0,
+///     12, 0, 1, b'm', 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 9,
+///     0,
+/// ];
+/// let tag: Tag = nbt::from_slice(&data).unwrap();
+/// assert_eq!(tag.get("m"), Some(&Tag::LongArray(vec![9])));
+/// assert_eq!(nbt::to_vec("", &tag, Compression::None).unwrap(), data);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// The tag type ID.
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    /// Looks up a field, if this is a compound.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        self.as_compound()?.get(key)
+    }
+
+    /// Any integer type, widened.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Any numeric type, widened.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(v) => Some(v as f64),
+            Tag::Double(v) => Some(v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(c) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Tag::Byte(v) => ser.serialize_i8(*v),
+            Tag::Short(v) => ser.serialize_i16(*v),
+            Tag::Int(v) => ser.serialize_i32(*v),
+            Tag::Long(v) => ser.serialize_i64(*v),
+            Tag::Float(v) => ser.serialize_f32(*v),
+            Tag::Double(v) => ser.serialize_f64(*v),
+            Tag::ByteArray(v) => ser.serialize_newtype_struct("*NBT_BYTEARRAY", v),
+            Tag::String(v) => ser.serialize_str(v),
+            Tag::List(v) => v.serialize(ser),
+            Tag::Compound(v) => v.serialize(ser),
+            Tag::IntArray(v) => ser.serialize_newtype_struct("*NBT_INTARRAY", v),
+            Tag::LongArray(v) => ser.serialize_newtype_struct("*NBT_LONGARRAY", v),
+        }
+    }
+}
+
+const VARIANTS: &[&str] = &[
+    "End", "Byte", "Short", "Int", "Long", "Float", "Double", "ByteArray", "String", "List",
+    "Compound", "IntArray", "LongArray",
+];
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Tag, D::Error> {
+        struct TagVisitor;
+        impl<'de> Visitor<'de> for TagVisitor {
+            type Value = Tag;
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an NBT tag")
+            }
+            fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<Tag, A::Error> {
+                let (id, v): (u8, _) = data.variant()?;
+                Ok(match id {
+                    BYTE => Tag::Byte(v.newtype_variant()?),
+                    SHORT => Tag::Short(v.newtype_variant()?),
+                    INT => Tag::Int(v.newtype_variant()?),
+                    LONG => Tag::Long(v.newtype_variant()?),
+                    FLOAT => Tag::Float(v.newtype_variant()?),
+                    DOUBLE => Tag::Double(v.newtype_variant()?),
+                    BYTE_ARRAY => Tag::ByteArray(v.newtype_variant()?),
+                    STRING => Tag::String(v.newtype_variant()?),
+                    LIST => Tag::List(v.newtype_variant()?),
+                    COMPOUND => Tag::Compound(v.newtype_variant()?),
+                    INT_ARRAY => Tag::IntArray(v.newtype_variant()?),
+                    LONG_ARRAY => Tag::LongArray(v.newtype_variant()?),
+                    id => return Err(de::Error::custom(format!("unknown tag type {}", id))),
+                })
+            }
+        }
+        // The NBT deserializer recognizes this name and gives the tag ID as the variant
+        de.deserialize_enum("*NBT_TAG", VARIANTS, TagVisitor)
+    }
+}
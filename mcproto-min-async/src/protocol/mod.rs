@@ -22,6 +22,33 @@ impl Packet for () {}
 pub enum ProtocolState {
     Handshake,
     Status,
+    Login,
+}
+
+/// Whatever is left of the packet, as is. Only makes sense as the last field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rest(pub Vec<u8>);
+
+impl Serialize for Rest {
+    fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_newtype_struct("*REST", serde_bytes::Bytes::new(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Rest {
+    fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Rest, D::Error> {
+        struct RestVisitor;
+        impl<'de> serde::de::Visitor<'de> for RestVisitor {
+            type Value = Rest;
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("the rest of the packet")
+            }
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Rest, E> {
+                Ok(Rest(v.to_vec()))
+            }
+        }
+        de.deserialize_newtype_struct("*REST", RestVisitor)
+    }
 }
 
 pub struct Handshake;
@@ -67,3 +94,332 @@ pub mod status {
     impl Packet for Clientbound {}
     impl Packet for Serverbound {}
 }
+
+/// Protocol versions where the login packets changed.
+pub mod version {
+    /// 1.16, Login Success sends the UUID as a number instead of a string
+    pub const BINARY_UUID: i32 = 735;
+    /// 1.19, Login Start gained an optional signing key
+    pub const SIGNING_KEY: i32 = 759;
+    /// 1.19 to 1.19.2, Encryption Response can have a signature made with the player's key instead
+    /// of the verify token
+    pub const SIGNED_ENCRYPTION: std::ops::RangeInclusive<i32> = 759..=760;
+    /// 1.19.3, Login Start sends an optional UUID instead of the signing key
+    pub const OPTIONAL_UUID: i32 = 761;
+    /// 1.20.2, Login Start always sends a UUID, and the client acknowledges Login Success
+    pub const LOGIN_ACKNOWLEDGED: i32 = 764;
+    /// 1.20.3, text components are sent as NBT instead of JSON
+    pub const NBT_TEXT: i32 = 765;
+    /// 1.20.5, Encryption Request says whether the client should check in with the session server
+    pub const SHOULD_AUTHENTICATE: i32 = 766;
+    /// 1.20.5 to 1.21.1, Login Success says whether the client should be strict about errors
+    pub const STRICT_ERRORS: std::ops::RangeInclusive<i32> = 766..=767;
+}
+
+pub struct Login;
+impl Protocol for Login {
+    type Serverbound = login::Serverbound;
+    type Clientbound = login::Clientbound;
+    const ID: u32 = 0;
+}
+/// Logging in. The fields that vary between versions are left as [`Rest`], with helpers to read
+/// and write them for a given protocol version.
+pub mod login {
+    use super::*;
+    use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+    use std::io::{self, Cursor, Read};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Clientbound {
+        /// The reason is a JSON text component
+        Disconnect { reason: String },
+        EncryptionRequest {
+            server_id: String,
+            #[serde(with="serde_bytes")]
+            public_key: Vec<u8>,
+            #[serde(with="serde_bytes")]
+            verify_token: Vec<u8>,
+            /// Whether the client should authenticate with Mojang, since 1.20.5
+            rest: Rest,
+        },
+        /// See [`Success::parse`]
+        LoginSuccess(Rest),
+        /// Packets after this are compressed, if they're at least this many bytes
+        SetCompression(#[serde(with="varint")] i32),
+        LoginPluginRequest {
+            #[serde(with="varint")]
+            message_id: i32,
+            channel: String,
+            data: Rest,
+        },
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Serverbound {
+        /// See [`Serverbound::login_start`] and [`Serverbound::player_uuid`]
+        LoginStart { name: String, rest: Rest },
+        EncryptionResponse {
+            #[serde(with="serde_bytes")]
+            shared_secret: Vec<u8>,
+            #[serde(with="serde_bytes")]
+            verify_token: Vec<u8>,
+        },
+        /// `None` tells the server the channel isn't understood
+        LoginPluginResponse {
+            #[serde(with="varint")]
+            message_id: i32,
+            data: Option<Rest>,
+        },
+        LoginAcknowledged,
+    }
+    impl Packet for Clientbound {}
+    impl Packet for Serverbound {}
+
+    fn read_string(r: &mut impl Read) -> io::Result<String> {
+        let len = crate::varint::read(&mut *r)?;
+        let mut buf = vec![0; len.max(0) as usize];
+        r.read_exact(&mut buf)?;
+        String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn parse_uuid(s: &str) -> io::Result<u128> {
+        u128::from_str_radix(&s.replace('-', ""), 16)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Whether a name is one an account can have: 1 to 16 letters, digits and underscores.
+    ///
+    /// ```
+    /// use mcproto_min_async::protocol::login::is_valid_name;
+    /// assert!(is_valid_name("Notch") && is_valid_name("jeb_") && is_valid_name("0123456789abcdef"));
+    /// assert!(!is_valid_name("") && !is_valid_name("0123456789abcdefg"));
+    /// assert!(!is_valid_name("a&serverId=x") && !is_valid_name("a b") && !is_valid_name("é"));
+    /// ```
+    pub fn is_valid_name(name: &str) -> bool {
+        (1..=16).contains(&name.len()) && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    }
+
+    /// The UUID offline-mode servers give a player: an MD5 (version 3) UUID of
+    /// `OfflinePlayer:<name>`.
+    ///
+    /// ```
+    /// use mcproto_min_async::protocol::login::offline_uuid;
+    /// assert_eq!(offline_uuid("Notch"), 0xb50ad385_829d_3141_a216_7e7d7539ba7f);
+    /// ```
+    pub fn offline_uuid(name: &str) -> u128 {
+        let mut bytes = md5::compute(format!("OfflinePlayer:{}", name)).0;
+        bytes[6] = (bytes[6] & 0x0f) | 0x30;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        u128::from_be_bytes(bytes)
+    }
+
+    /// What Login Success says about the player.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Success {
+        pub uuid: u128,
+        pub name: String,
+    }
+
+    impl Success {
+        pub fn parse(rest: &Rest, version: i32) -> io::Result<Success> {
+            let mut r = Cursor::new(&rest.0[..]);
+            let uuid = if version >= version::BINARY_UUID {
+                r.read_u128::<BE>()?
+            } else {
+                parse_uuid(&read_string(&mut r)?)?
+            };
+            // Skin properties follow since 1.19, which nothing here needs
+            Ok(Success { uuid, name: read_string(&mut r)? })
+        }
+
+        pub fn to_packet(&self, version: i32) -> Clientbound {
+            let mut rest = Vec::new();
+            if version >= version::BINARY_UUID {
+                rest.write_u128::<BE>(self.uuid).unwrap();
+            } else {
+                let hex = format!("{:032x}", self.uuid);
+                let dashed = format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]);
+                crate::ser::to_writer(&mut rest, &dashed);
+            }
+            crate::ser::to_writer(&mut rest, &self.name);
+            if version >= version::SIGNING_KEY {
+                // No properties
+                rest.push(0);
+            }
+            if version::STRICT_ERRORS.contains(&version) {
+                rest.push(0);
+            }
+            Clientbound::LoginSuccess(Rest(rest))
+        }
+    }
+
+    impl Serverbound {
+        /// Builds Login Start the way `version` expects it.
+        pub fn login_start(name: &str, uuid: u128, version: i32) -> Serverbound {
+            let mut rest = Vec::new();
+            if version >= version::LOGIN_ACKNOWLEDGED {
+                rest.write_u128::<BE>(uuid).unwrap();
+            } else if version >= version::OPTIONAL_UUID {
+                rest.push(1);
+                rest.write_u128::<BE>(uuid).unwrap();
+            } else if version >= version::SIGNING_KEY {
+                // No signing key, and no UUID either from 1.19.1 on
+                rest.push(0);
+                if version > version::SIGNING_KEY {
+                    rest.push(0);
+                }
+            }
+            Serverbound::LoginStart { name: name.to_string(), rest: Rest(rest) }
+        }
+
+        /// The UUID the client sent with Login Start, if the version has one.
+        pub fn player_uuid(rest: &Rest, version: i32) -> Option<u128> {
+            let mut r = Cursor::new(&rest.0[..]);
+            // Before 1.20.2 there's a flag for whether it's there
+            let present = version >= version::LOGIN_ACKNOWLEDGED
+                || (version >= version::OPTIONAL_UUID && r.read_u8().ok()? == 1);
+            if present { r.read_u128::<BE>().ok() } else { None }
+        }
+    }
+}
+
+/// A packet left as its ID and body, for states whose packet IDs change with every version.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Generic {
+    #[serde(with="varint")]
+    pub id: i32,
+    pub data: Rest,
+}
+impl Packet for Generic {}
+
+impl Generic {
+    pub fn new(id: i32, data: Vec<u8>) -> Self {
+        Generic { id, data: Rest(data) }
+    }
+}
+
+/// The text of a disconnect reason: a JSON text component, or NBT since 1.20.3.
+pub fn disconnect_reason(data: &Rest, version: i32) -> String {
+    if version < version::NBT_TEXT {
+        return match crate::de::from_slice::<String>(&data.0) {
+            Ok(json) => crate::text::plain_or_raw(&json),
+            Err(_) => "(unreadable reason)".to_string(),
+        };
+    }
+    match crate::nbt::from_network_slice::<crate::text::TextComponent>(&data.0) {
+        Ok(reason) => reason.to_plain(),
+        Err(_) => "(unreadable reason)".to_string(),
+    }
+}
+
+/// Setting up the client between logging in and playing, since 1.20.2. Packets are [`Generic`],
+/// with IDs from [`configuration::ids`].
+pub struct Configuration;
+impl Protocol for Configuration {
+    type Serverbound = Generic;
+    type Clientbound = Generic;
+    const ID: u32 = 0;
+}
+pub mod configuration {
+    use super::*;
+
+    /// The packets a client needs to get through configuration.
+    #[derive(Debug, Clone)]
+    pub struct Ids {
+        pub disconnect: i32,
+        pub finish: i32,
+        pub keep_alive: i32,
+        pub ping: i32,
+        /// Since 1.20.5
+        pub known_packs: Option<i32>,
+        pub acknowledge_finish: i32,
+        pub keep_alive_response: i32,
+        pub pong: i32,
+        pub known_packs_response: Option<i32>,
+    }
+
+    /// Packet IDs for a protocol version, or `None` if it doesn't have a configuration state.
+    pub fn ids(version: i32) -> Option<Ids> {
+        match version {
+            v if v < version::LOGIN_ACKNOWLEDGED => None,
+            764..=765 => Some(Ids {
+                disconnect: 0x01, finish: 0x02, keep_alive: 0x03, ping: 0x04, known_packs: None,
+                acknowledge_finish: 0x02, keep_alive_response: 0x03, pong: 0x04, known_packs_response: None,
+            }),
+            _ => Some(Ids {
+                disconnect: 0x02, finish: 0x03, keep_alive: 0x04, ping: 0x05, known_packs: Some(0x0E),
+                acknowledge_finish: 0x03, keep_alive_response: 0x04, pong: 0x05, known_packs_response: Some(0x07),
+            }),
+        }
+    }
+}
+
+/// Playing. Packets are [`Generic`], and only the few in [`play::ids`] are known.
+pub struct Play;
+impl Protocol for Play {
+    type Serverbound = Generic;
+    type Clientbound = Generic;
+    const ID: u32 = 0;
+}
+pub mod play {
+    use super::*;
+    use byteorder::{WriteBytesExt, BE};
+
+    #[derive(Debug, Clone)]
+    pub struct Ids {
+        /// Join Game, or Login (play), which is always the first packet
+        pub join_game: i32,
+        pub disconnect: i32,
+        /// Serverbound Chat Message
+        pub chat: i32,
+    }
+
+    /// Packet IDs from 1.14.4 to 1.21.5, or `None` for other versions.
+    pub fn ids(version: i32) -> Option<Ids> {
+        let (join_game, disconnect, chat) = match version {
+            498 => (0x25, 0x1B, 0x03),
+            573..=578 => (0x26, 0x1B, 0x03),
+            735..=736 => (0x25, 0x1A, 0x03),
+            751..=754 => (0x24, 0x19, 0x03),
+            755..=758 => (0x26, 0x1A, 0x03),
+            759 => (0x23, 0x17, 0x04),
+            760 => (0x25, 0x19, 0x05),
+            761 => (0x24, 0x17, 0x05),
+            762..=763 => (0x28, 0x1A, 0x05),
+            764..=765 => (0x29, 0x1B, 0x05),
+            766..=767 => (0x2B, 0x1D, 0x06),
+            768..=769 => (0x2C, 0x1D, 0x07),
+            770 => (0x2B, 0x1C, 0x07),
+            _ => return None,
+        };
+        Some(Ids { join_game, disconnect, chat })
+    }
+
+    /// An unsigned chat message, which servers accept from offline-mode players.
+    pub fn chat_message(text: &str, version: i32) -> Option<Generic> {
+        let ids = ids(version)?;
+        let mut data = Vec::new();
+        crate::ser::to_writer(&mut data, &text);
+        if version >= version::SIGNING_KEY {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+            data.write_i64::<BE>(now.as_millis() as i64).unwrap();
+            // Salt
+            data.write_i64::<BE>(0).unwrap();
+            match version {
+                // Empty signature, not previewed
+                759 => data.extend_from_slice(&[0, 0]),
+                // And no previous messages
+                760 => data.extend_from_slice(&[0, 0, 0, 0]),
+                // No signature, no messages acknowledged, and a 20 bit set of them
+                _ => {
+                    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+                    if version >= 770 {
+                        // Checksum, which 0 skips checking
+                        data.push(0);
+                    }
+                }
+            }
+        }
+        Some(Generic::new(ids.chat, data))
+    }
+}
@@ -0,0 +1,38 @@
+//! Passing connections through to another server once the handshake has been looked at.
+//!
+//! Read the handshake with [`Connection::new`](crate::server::Connection::new), make a connection
+//! to the backend and send it the same handshake with
+//! [`Client::handshake`](crate::server::Client::handshake), and then [`splice`] the two together.
+//! This has to happen before compression or encryption start, which is fine, since the backend
+//! is the one that would turn them on.
+
+use futures::future;
+use tokio::io::{self, AsyncWriteExt};
+
+use crate::protocol::Protocol;
+use crate::server::{Client, Transport};
+
+/// Copies everything from each connection to the other until both sides are done, including what
+/// each had buffered. Returns how many bytes went each way, client to server first.
+pub async fn splice<P: Protocol, P2: Protocol, S: Transport, S2: Transport>(
+    client: Client<P, S>, server: Client<P2, S2>,
+) -> Result<(u64, u64), io::Error> {
+    let (client, client_buffered) = client.into_inner();
+    let (server, server_buffered) = server.into_inner();
+    let (mut client_read, mut client_write) = io::split(client);
+    let (mut server_read, mut server_write) = io::split(server);
+    let upstream = async {
+        server_write.write_all(&client_buffered).await?;
+        let n = io::copy(&mut client_read, &mut server_write).await?;
+        // Pass the half-close along
+        server_write.shutdown().await?;
+        Ok::<_, io::Error>(client_buffered.len() as u64 + n)
+    };
+    let downstream = async {
+        client_write.write_all(&server_buffered).await?;
+        let n = io::copy(&mut server_read, &mut client_write).await?;
+        client_write.shutdown().await?;
+        Ok::<_, io::Error>(server_buffered.len() as u64 + n)
+    };
+    future::try_join(upstream, downstream).await
+}
@@ -0,0 +1,178 @@
+//! HAProxy's PROXY protocol, which load balancers use to say who the client really is.
+//!
+//! The header comes before anything else on the connection, so it's read straight off the socket
+//! before the codec gets it. Both versions are read: the text one (v1) and the binary one (v2).
+//! Only TCP over IPv4 and IPv6 carry an address; anything else, like the proxy's own health
+//! checks, is accepted without one.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest a v1 header can be, CRLF included
+const V1_MAX: usize = 107;
+/// How many bytes of TLVs a v2 header can have after its addresses. Proxies send a few short
+/// ones at most, like a connection ID or the TLS details.
+const V2_TLV_MAX: usize = 1024;
+
+fn bad_header(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Bad PROXY protocol header: {}", msg))
+}
+
+/// Reads the header, giving the client's address if the proxy passed one along.
+///
+/// ```
+/// use mcproto_min_async::proxy_protocol::{header_v1, header_v2, read_header};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let src = "[2001:db8::1]:56324".parse().unwrap();
+/// let dst = "[2001:db8::2]:25565".parse().unwrap();
+/// assert_eq!(read_header(&mut &header_v1(src, dst)[..]).await.unwrap(), Some(src));
+/// assert_eq!(read_header(&mut &header_v2(src, dst)[..]).await.unwrap(), Some(src));
+///
+/// let v2 = |header: &[u8]| [b"\r\n\r\n\0\r\nQUIT\n" as &[u8], header].concat();
+/// // LOCAL, with nothing after it
+/// assert_eq!(read_header(&mut &v2(&[0x20, 0, 0, 0])[..]).await.unwrap(), None);
+/// // Version 3, command 2, address family 4 and transport protocol 3 don't exist
+/// for bad in [[0x31, 0x11], [0x22, 0x11], [0x21, 0x41], [0x21, 0x13]] {
+///     assert!(read_header(&mut &v2(&[bad[0], bad[1], 0, 12])[..]).await.is_err());
+/// }
+/// // Too long to be a real header, and too short to hold an IPv4 address
+/// assert!(read_header(&mut &v2(&[0x21, 0x11, 0xff, 0xff])[..]).await.is_err());
+/// assert!(read_header(&mut &v2(&[0x21, 0x11, 0, 4, 192, 0, 2, 1])[..]).await.is_err());
+/// # }
+/// ```
+pub async fn read_header<R: AsyncRead + Unpin>(r: &mut R) -> Result<Option<SocketAddr>, io::Error> {
+    // Shorter than either version's shortest header, so it doesn't read into the handshake
+    let mut start = [0; 12];
+    r.read_exact(&mut start).await?;
+    if &start == V2_SIGNATURE {
+        read_v2(r).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(r, &start).await
+    } else {
+        Err(bad_header("missing"))
+    }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(r: &mut R, start: &[u8]) -> Result<Option<SocketAddr>, io::Error> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() == V1_MAX {
+            return Err(bad_header("too long"));
+        }
+        line.push(r.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| bad_header("not text"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4", src, _dst, sport, _dport] | ["PROXY", "TCP6", src, _dst, sport, _dport] => {
+            let ip: IpAddr = src.parse().map_err(|_| bad_header("bad address"))?;
+            let port: u16 = sport.parse().map_err(|_| bad_header("bad port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(bad_header(line)),
+    }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(r: &mut R) -> Result<Option<SocketAddr>, io::Error> {
+    let version_command = r.read_u8().await?;
+    if version_command >> 4 != 2 {
+        return Err(bad_header("unknown version"));
+    }
+    let command = version_command & 0xF;
+    if command > 1 {
+        return Err(bad_header("unknown command"));
+    }
+    let family = r.read_u8().await?;
+    // The address block's size for each address family: none, IPv4, IPv6 and unix sockets
+    let addresses = match family >> 4 {
+        0 => 0,
+        1 => 12,
+        2 => 36,
+        3 => 216,
+        _ => return Err(bad_header("unknown address family")),
+    };
+    if family & 0xF > 2 {
+        return Err(bad_header("unknown transport protocol"));
+    }
+    // Checked before anything gets allocated
+    let len = r.read_u16().await? as usize;
+    if len > addresses + V2_TLV_MAX {
+        return Err(bad_header("too long"));
+    }
+    if command == 1 && len < addresses {
+        return Err(bad_header("addresses cut short"));
+    }
+    // Addresses, and then any TLVs, which are skipped
+    let mut data = vec![0; len];
+    r.read_exact(&mut data).await?;
+    match (command, family) {
+        // LOCAL: the proxy talking for itself
+        (0, _) => Ok(None),
+        // PROXY over TCP4
+        (_, 0x11) => {
+            let ip = Ipv4Addr::new(data[0], data[1], data[2], data[3]);
+            let port = u16::from_be_bytes([data[8], data[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // PROXY over TCP6
+        (_, 0x21) => {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&data[..16]);
+            let port = u16::from_be_bytes([data[32], data[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        // Other protocols, like UDP and unix sockets
+        _ => Ok(None),
+    }
+}
+
+/// Makes a v1 header for a connection from `src` to `dst`.
+///
+/// ```
+/// use mcproto_min_async::proxy_protocol::header_v1;
+/// let src = "192.0.2.1:56324".parse().unwrap();
+/// let dst = "198.51.100.7:25565".parse().unwrap();
+/// assert_eq!(header_v1(src, dst), b"PROXY TCP4 192.0.2.1 198.51.100.7 56324 25565\r\n");
+/// ```
+pub fn header_v1(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let family = match (src, dst) {
+        (SocketAddr::V4(_), SocketAddr::V4(_)) => "TCP4",
+        (SocketAddr::V6(_), SocketAddr::V6(_)) => "TCP6",
+        _ => return b"PROXY UNKNOWN\r\n".to_vec(),
+    };
+    format!("PROXY {} {} {} {} {}\r\n", family, src.ip(), dst.ip(), src.port(), dst.port()).into_bytes()
+}
+
+/// Makes a v2 header for a connection from `src` to `dst`.
+///
+/// ```
+/// use mcproto_min_async::proxy_protocol::header_v2;
+/// let src = "192.0.2.1:56324".parse().unwrap();
+/// let dst = "198.51.100.7:25565".parse().unwrap();
+/// assert_eq!(header_v2(src, dst), [
+///     b"\r\n\r\n\0\r\nQUIT\n" as &[u8],
+///     &[0x21, 0x11, 0, 12, 192, 0, 2, 1, 198, 51, 100, 7, 0xDC, 0x04, 0x63, 0xDD],
+/// ].concat());
+/// ```
+pub fn header_v2(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    let (family, addresses) = match (src, dst) {
+        (SocketAddr::V4(s), SocketAddr::V4(d)) => (0x11, [&s.ip().octets()[..], &d.ip().octets()[..]].concat()),
+        (SocketAddr::V6(s), SocketAddr::V6(d)) => (0x21, [&s.ip().octets()[..], &d.ip().octets()[..]].concat()),
+        _ => {
+            // Can't say, so it's just the proxy
+            header.extend_from_slice(&[0x20, 0, 0, 0]);
+            return header;
+        }
+    };
+    header.extend_from_slice(&[0x21, family]);
+    header.extend_from_slice(&(addresses.len() as u16 + 4).to_be_bytes());
+    header.extend_from_slice(&addresses);
+    header.extend_from_slice(&src.port().to_be_bytes());
+    header.extend_from_slice(&dst.port().to_be_bytes());
+    header
+}
@@ -119,6 +119,9 @@ build_ser! {
             "*VARINT" => {  // make sure that you can't do this by accident by adding an asterisk there
                 ser::Serialize::serialize(v, &mut SpecialSerializer { w: self, mode: Mode::VarInt })
             },
+            "*VARLONG" => {
+                ser::Serialize::serialize(v, &mut SpecialSerializer { w: self, mode: Mode::VarLong })
+            },
             "*REST" => {
                 ser::Serialize::serialize(v, &mut SpecialSerializer { w: self, mode: Mode::Rest })
             },
@@ -161,25 +164,34 @@ build_ser! {
 #[derive(Debug,PartialEq)]
 enum Mode {
     VarInt,
+    VarLong,
     Rest,
     IntPrefixed,
     ShortPrefixed
 }
 
 struct SpecialSerializer<'de, W: Write> { w: &'de mut Serializer<W>, mode: Mode }
+impl<'de, W: Write> SpecialSerializer<'de, W> {
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        match self.mode {
+            Mode::VarLong => self.w.w.write_all(&*varint::encode_long(v)),
+            _ => self.w.w.write_all(&*varint::encode(v as i32)),
+        }
+    }
+}
 build_ser! {
     ['de, W: Write] &'de mut SpecialSerializer<'de, W>, &'de mut Serializer<W> => self, v:
     serialize_bool: bool => Err(Error::Any),
-    serialize_i8: i8 =>   self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_u8: u8 =>   self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_i16: i16 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_u16: u16 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_i32: i32 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_u32: u32 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_i64: i64 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_u64: u64 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_f32: f32 => self.w.w.write_all(&*varint::encode(v as i32)),
-    serialize_f64: f64 => self.w.w.write_all(&*varint::encode(v as i32)),
+    serialize_i8: i8 =>   self.write_number(v as i64),
+    serialize_u8: u8 =>   self.write_number(v as i64),
+    serialize_i16: i16 => self.write_number(v as i64),
+    serialize_u16: u16 => self.write_number(v as i64),
+    serialize_i32: i32 => self.write_number(v as i64),
+    serialize_u32: u32 => self.write_number(v as i64),
+    serialize_i64: i64 => self.write_number(v as i64),
+    serialize_u64: u64 => self.write_number(v as i64),
+    serialize_f32: f32 => self.write_number(v as i64),
+    serialize_f64: f64 => self.write_number(v as i64),
     serialize_char: char => Err(Error::Type("char")),
     serialize_str: &str => Err(Error::Type("str")),
     serialize_bytes: &[u8] => self.w.w.write_all(v),
@@ -1,60 +1,98 @@
 //! An implementation of a minecraft server packet parser. This module allows you to create and
 //! handshake a client.
 
+use std::net::SocketAddr;
+
 use futures::prelude::*;
 
 //use tokio::prelude::*;
-use tokio::io;
+use tokio::io::{self, AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 
 use tokio_util::codec::{FramedRead,FramedWrite};
-use crate::codec::{RawPacket, Codec};
+use bytes::BytesMut;
+use crate::codec::{self, RawPacket, Codec};
+use crate::de;
 use crate::ser;
+use crate::auth;
+use crate::proxy_protocol;
+use crate::text;
 use crate::protocol::{self,*};
 
 
-type ReadHalf = io::ReadHalf<TcpStream>;
-type WriteHalf = io::WriteHalf<TcpStream>;
+/// Anything a client can talk over: TCP, a Unix socket, an in-memory [`io::duplex`] pipe, a TLS
+/// stream, and so on. Clients default to TCP.
+///
+/// ```
+/// use mcproto_min_async::server::{Client, Connection, ConnectionSettings};
+/// use mcproto_min_async::protocol::{status, Handshake, ProtocolState, Status};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// let (ours, theirs) = tokio::io::duplex(1024);
+/// let server = tokio::spawn(async move {
+///     if let Connection::Status(mut client) = Connection::with_settings(theirs, &ConnectionSettings::new()).await? {
+///         client.read().await?;
+///         client.write(&status::Clientbound::ServerListResp { data: "{}".to_string() }).await?;
+///     }
+///     Ok::<_, std::io::Error>(())
+/// });
+///
+/// let client = Client::create(ours, Handshake);
+/// let mut client = client.handshake(766, "localhost", 25565, ProtocolState::Status, Status).await?;
+/// client.write(&status::Serverbound::Request).await?;
+/// match client.read_cb().await? {
+///     status::Clientbound::ServerListResp { data } => assert_eq!(data, "{}"),
+///     p => panic!("{:?}", p),
+/// }
+/// server.await??;
+/// # Ok(())
+/// # }
+/// ```
+pub trait Transport: AsyncRead + AsyncWrite + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Unpin> Transport for T {}
 
-pub struct ClientReader<P>(pub FramedRead<ReadHalf, Codec>, pub P);
-pub struct ClientWriter(pub FramedWrite<WriteHalf, Codec>);
+pub struct ClientReader<P, S = TcpStream>(pub FramedRead<io::ReadHalf<S>, Codec>, pub P, pub de::Limits);
+pub struct ClientWriter<S = TcpStream>(pub FramedWrite<io::WriteHalf<S>, Codec>);
 
-impl<P> std::ops::Deref for ClientReader<P> {
-    type Target = FramedRead<ReadHalf,Codec>;
+impl<P, S> std::ops::Deref for ClientReader<P, S> {
+    type Target = FramedRead<io::ReadHalf<S>, Codec>;
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
-impl<P> std::ops::DerefMut for ClientReader<P> {
+impl<P, S> std::ops::DerefMut for ClientReader<P, S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
-impl std::ops::Deref for ClientWriter {
-    type Target = FramedWrite<WriteHalf, Codec>;
+impl<S> std::ops::Deref for ClientWriter<S> {
+    type Target = FramedWrite<io::WriteHalf<S>, Codec>;
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
-impl std::ops::DerefMut for ClientWriter {
+impl<S> std::ops::DerefMut for ClientWriter<S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
 
-impl<P: Protocol> ClientReader<P> {
+impl<P: Protocol, S: Transport> ClientReader<P, S> {
     pub async fn read(&mut self) -> Result<P::Serverbound,io::Error> {
-        Ok(self.read_raw().await?.into_serverbound::<P>()?)
+        Ok(de::from_slice_with_limits(&self.read_raw().await?, self.2)?)
+    }
+    pub async fn read_cb(&mut self) -> Result<P::Clientbound,io::Error> {
+        Ok(de::from_slice_with_limits(&self.read_raw().await?, self.2)?)
     }
     pub async fn read_raw(&mut self) -> Result<RawPacket,io::Error> {
         self.next().await.ok_or(io::ErrorKind::UnexpectedEof)?
     }
-    pub fn set_protocol<P2: Protocol>(self, protocol: P2) -> ClientReader<P2> {
-        ClientReader(self.0, protocol)
+    pub fn set_protocol<P2: Protocol>(self, protocol: P2) -> ClientReader<P2, S> {
+        ClientReader(self.0, protocol, self.2)
     }
 }
-impl ClientWriter {
-    pub async fn write<S: Packet>(&mut self, s: &S) -> Result<(),io::Error> {
+impl<S: Transport> ClientWriter<S> {
+    pub async fn write<T: Packet>(&mut self, s: &T) -> Result<(),io::Error> {
         let data = ser::to_bytes(&s);
         self.send(RawPacket(data)).await?;
         Ok(())
@@ -65,21 +103,42 @@ impl ClientWriter {
     }
 }
 
-pub enum Connection {
-    Status(Client<Status>),
+pub enum Connection<S = TcpStream> {
+    Status(Client<Status, S>),
+    Login(Client<Login, S>),
 }
 
-pub struct Client<P> {
-    pub read:  ClientReader<P>,
-    pub write: ClientWriter,
+pub struct Client<P, S = TcpStream> {
+    pub read:  ClientReader<P, S>,
+    pub write: ClientWriter<S>,
     version: i32,
     host: String,
-    port: u16
+    port: u16,
+    addr: Option<SocketAddr>,
 }
 impl Connection {
     /// Attempt to handshake a client.
     pub async fn new(s: TcpStream) -> Result<Connection, io::Error> {
+        let addr = s.peer_addr().ok();
+        let mut conn = Self::with_settings(s, &ConnectionSettings::new()).await?;
+        match conn {
+            Connection::Status(ref mut c) => c.addr = addr,
+            Connection::Login(ref mut c) => c.addr = addr,
+        }
+        Ok(conn)
+    }
+}
+impl<S: Transport> Connection<S> {
+    /// Attempt to handshake a client, after a PROXY protocol header if the settings ask for one.
+    pub async fn with_settings(mut s: S, settings: &ConnectionSettings) -> Result<Connection<S>, io::Error> {
+        let proxied = if settings.proxy_protocol {
+            proxy_protocol::read_header(&mut s).await?
+        } else {
+            None
+        };
         let mut client = Client::create(s, protocol::Handshake);
+        client.addr = proxied;
+        client.set_limits(settings);
 
         let protocol::handshake::Serverbound::ServerListPing {
             next_state, version, host, port
@@ -87,57 +146,373 @@ impl Connection {
         client.version = version;
         client.host = host;
         client.port = port;
-        if next_state == protocol::ProtocolState::Status {
-            Ok(Connection::Status(client.set_protocol(Status)))
-        } else {
-            Err(io::Error::new(io::ErrorKind::Other, "This library does not support logging in"))
+        match next_state {
+            protocol::ProtocolState::Status => Ok(Connection::Status(client.set_protocol(Status))),
+            protocol::ProtocolState::Login => Ok(Connection::Login(client.set_protocol(Login))),
+            protocol::ProtocolState::Handshake =>
+                Err(io::Error::new(io::ErrorKind::InvalidData, "Client asked for the handshake state again")),
         }
     }
 }
 
-#[derive(Default)]
+/// How to accept connections.
+///
+/// ```
+/// use mcproto_min_async::server::{Client, Connection, ConnectionSettings};
+/// use mcproto_min_async::protocol::{Handshake, ProtocolState, Status};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// let (ours, theirs) = tokio::io::duplex(1024);
+/// let settings = ConnectionSettings::new().set_max_string_len(255);
+/// let server = tokio::spawn(async move { Connection::with_settings(theirs, &settings).await.is_ok() });
+/// let host = "a".repeat(256);
+/// Client::create(ours, Handshake).handshake(766, &host, 25565, ProtocolState::Status, Status).await?;
+/// assert!(!server.await?);
+/// # Ok(())
+/// # }
+/// ```
 pub struct ConnectionSettings {
-    compression: Option<u32>
+    compression: Option<u32>,
+    proxy_protocol: bool,
+    max_frame_len: usize,
+    max_uncompressed_len: usize,
+    max_string_len: usize,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        ConnectionSettings {
+            compression: None,
+            proxy_protocol: false,
+            max_frame_len: codec::MAX_FRAME_LEN,
+            max_uncompressed_len: codec::MAX_UNCOMPRESSED_LEN,
+            max_string_len: de::MAX_STRING_LEN,
+        }
+    }
 }
 
 impl ConnectionSettings {
     pub fn new() -> Self {
         Self::default()
     }
+    /// Refuses packets longer than this, as sent. Defaults to [`codec::MAX_FRAME_LEN`].
+    pub fn set_max_frame_len(mut self, len: usize) -> Self {
+        self.max_frame_len = len;
+        self
+    }
+    /// Refuses compressed packets that say they're longer than this once decompressed. Defaults
+    /// to [`codec::MAX_UNCOMPRESSED_LEN`].
+    pub fn set_max_uncompressed_len(mut self, len: usize) -> Self {
+        self.max_uncompressed_len = len;
+        self
+    }
+    /// Refuses strings in packets longer than this many UTF-16 code units. Defaults to
+    /// [`de::MAX_STRING_LEN`].
+    pub fn set_max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = len;
+        self
+    }
     pub fn set_compression(mut self, threshold: u32) -> Self {
         self.compression = Some(threshold);
         self
     }
+    /// Expects every connection to start with a PROXY protocol header, for when the server is
+    /// behind a load balancer. Don't turn it on otherwise, or clients can say they're anyone.
+    pub fn accept_proxy_protocol(mut self) -> Self {
+        self.proxy_protocol = true;
+        self
+    }
 }
 
-impl<P: Protocol> Client<P> {
-    pub fn create(s: TcpStream, protocol: P) -> Self {
+impl<P: Protocol, S: Transport> Client<P, S> {
+    pub fn create(s: S, protocol: P) -> Self {
         let (read,write) = tokio::io::split(s);
-        let read = ClientReader(FramedRead::new(read, Codec::new()), protocol);
+        let read = ClientReader(FramedRead::new(read, Codec::new()), protocol, de::Limits::default());
         let write = ClientWriter(FramedWrite::new(write, Codec::new()));
 
         Client {
-            read, write, version: 0, host: String::new(), port: 0
+            read, write, version: 0, host: String::new(), port: 0, addr: None
         }
     }
     pub async fn read(&mut self) -> Result<P::Serverbound, io::Error> {
-        Ok(self.read.read_raw().await?.into_serverbound::<P>()?)
+        self.read.read().await
     }
     pub async fn read_cb(&mut self) -> Result<P::Clientbound, io::Error> {
-        Ok(self.read.read_raw().await?.into_clientbound::<P>()?)
+        self.read.read_cb().await
     }
     pub async fn read_raw(&mut self) -> Result<RawPacket, io::Error> {
         self.read.read_raw().await
     }
-    pub async fn write<S: Packet>(&mut self, s: &S) -> Result<(),io::Error> {
+    pub async fn write<T: Packet>(&mut self, s: &T) -> Result<(),io::Error> {
         self.write.write(s).await
     }
     pub async fn write_raw(&mut self, data: &RawPacket) -> Result<(),io::Error> {
         self.write.write_raw(data).await
     }
-    pub fn set_protocol<P2: Protocol>(self, protocol: P2) -> Client<P2> {
-        let Self { read, write, version, host, port } = self;
+    /// Takes the connection back, for passing it along as it is. Anything that was read but not
+    /// decoded yet comes with it. Meaningless once compression or encryption is on.
+    pub fn into_inner(self) -> (S, BytesMut) {
+        let buffered = self.read.0.read_buffer().clone();
+        let stream = self.read.0.into_inner().unsplit(self.write.0.into_inner());
+        (stream, buffered)
+    }
+    pub fn set_protocol<P2: Protocol>(self, protocol: P2) -> Client<P2, S> {
+        let Self { read, write, version, host, port, addr } = self;
         let read = read.set_protocol(protocol);
-        Client { read, write, version, host, port }
+        Client { read, write, version, host, port, addr }
+    }
+    /// Applies the size limits from the settings to what's read from now on.
+    pub fn set_limits(&mut self, settings: &ConnectionSettings) {
+        let codec = self.read.decoder_mut();
+        codec.set_max_frame_len(settings.max_frame_len);
+        codec.set_max_uncompressed_len(settings.max_uncompressed_len);
+        self.read.2 = de::Limits { max_string_len: settings.max_string_len };
+    }
+    /// The protocol version from the handshake.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+    /// The address the client connected to, from the handshake.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    /// The other end's address, as given by the PROXY protocol header if there was one. Otherwise
+    /// it's only known for TCP connections from [`Connection::new`], or if it's been set.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+    pub fn set_addr(&mut self, addr: SocketAddr) {
+        self.addr = Some(addr);
+    }
+    /// Compresses packets of at least `threshold` bytes in both directions from now on. A
+    /// negative threshold leaves compression off, like the server does.
+    pub fn enable_compression(&mut self, threshold: i32) {
+        if threshold >= 0 {
+            self.read.decoder_mut().set_compression(threshold);
+            self.write.encoder_mut().set_compression(threshold);
+        }
+    }
+    /// Encrypts everything in both directions from now on, once the key exchange is done.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8; 16]) {
+        self.read.decoder_mut().set_encryption(shared_secret);
+        self.write.encoder_mut().set_encryption(shared_secret);
+    }
+}
+
+impl<S: Transport> Client<Handshake, S> {
+    /// Sends the handshake, as a client, and moves on to the next state.
+    pub async fn handshake<P2: Protocol>(
+        mut self, version: i32, host: &str, port: u16, next_state: protocol::ProtocolState, protocol: P2,
+    ) -> Result<Client<P2, S>, io::Error> {
+        self.write(&protocol::handshake::Serverbound::ServerListPing {
+            version, host: host.to_string(), port, next_state,
+        }).await?;
+        self.version = version;
+        self.host = host.to_string();
+        self.port = port;
+        Ok(self.set_protocol(protocol))
+    }
+}
+
+fn disconnected(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, format!("Disconnected: {}", reason))
+}
+
+/// Logging in, from either side.
+impl<S: Transport> Client<Login, S> {
+    /// Reads Login Start, giving the player's name and the UUID they sent, if any. Names an
+    /// account couldn't have are refused.
+    pub async fn read_login_start(&mut self) -> Result<(String, Option<u128>), io::Error> {
+        match self.read().await? {
+            login::Serverbound::LoginStart { name, .. } if !login::is_valid_name(&name) =>
+                Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid player name {:?}", name))),
+            login::Serverbound::LoginStart { name, rest } => {
+                let uuid = login::Serverbound::player_uuid(&rest, self.version);
+                Ok((name, uuid))
+            }
+            p => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected Login Start, got {:?}", p))),
+        }
+    }
+    /// Tells the client to compress packets of at least `threshold` bytes, and starts doing so.
+    pub async fn set_compression(&mut self, threshold: i32) -> Result<(), io::Error> {
+        self.write(&login::Clientbound::SetCompression(threshold)).await?;
+        self.enable_compression(threshold);
+        Ok(())
+    }
+    /// Finishes logging in, waiting for the client to acknowledge it on versions that do.
+    pub async fn login_success(&mut self, success: &login::Success) -> Result<(), io::Error> {
+        self.write(&success.to_packet(self.version)).await?;
+        if self.version >= protocol::version::LOGIN_ACKNOWLEDGED {
+            match self.read().await? {
+                login::Serverbound::LoginAcknowledged => (),
+                p => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected Login Acknowledged, got {:?}", p))),
+            }
+        }
+        Ok(())
+    }
+    /// Does the key exchange for an online-mode login and turns on encryption. With a session
+    /// server, the client's account is checked with it, and its profile is returned.
+    ///
+    /// 1.19 to 1.19.2 clients are refused before anything is sent, since their Encryption
+    /// Response is in a different format that can be signed with the player's key.
+    pub async fn encrypt(
+        &mut self, key: &auth::ServerKey, name: &str, session_host: Option<&str>,
+    ) -> Result<Option<auth::Profile>, io::Error> {
+        if !login::is_valid_name(name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid player name {:?}", name)));
+        }
+        if protocol::version::SIGNED_ENCRYPTION.contains(&self.version) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported,
+                format!("Encryption isn't supported for protocol version {} (1.19 to 1.19.2)", self.version)));
+        }
+        let verify_token: [u8; 4] = rand::random();
+        let mut rest = Vec::new();
+        if self.version >= protocol::version::SHOULD_AUTHENTICATE {
+            rest.push(session_host.is_some() as u8);
+        }
+        self.write(&login::Clientbound::EncryptionRequest {
+            server_id: String::new(),
+            public_key: key.public_der().to_vec(),
+            verify_token: verify_token.to_vec(),
+            rest: protocol::Rest(rest),
+        }).await?;
+        let (secret, token) = match self.read().await? {
+            login::Serverbound::EncryptionResponse { shared_secret, verify_token } =>
+                (key.decrypt(&shared_secret)?, key.decrypt(&verify_token)?),
+            p => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected Encryption Response, got {:?}", p))),
+        };
+        if token != verify_token {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Client sent the wrong verify token"));
+        }
+        let mut shared_secret = [0; 16];
+        if secret.len() != shared_secret.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Client sent a shared secret of the wrong size"));
+        }
+        shared_secret.copy_from_slice(&secret);
+        self.enable_encryption(&shared_secret);
+        match session_host {
+            Some(host) => {
+                let hash = auth::server_hash("", &shared_secret, key.public_der());
+                match auth::has_joined(host, name, &hash).await? {
+                    Some(profile) => Ok(Some(profile)),
+                    None => Err(io::Error::new(io::ErrorKind::PermissionDenied, "Failed to verify username")),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+    /// Kicks the client. `reason` is a JSON text component.
+    pub async fn disconnect(&mut self, reason: &str) -> Result<(), io::Error> {
+        self.write(&login::Clientbound::Disconnect { reason: reason.to_string() }).await
+    }
+
+    /// Logs in to an offline-mode server, as a client. Compression is turned on if the server
+    /// asks for it, and plugin requests are answered as not understood. Fails if the server is in
+    /// online mode or kicks us.
+    pub async fn login_offline(&mut self, name: &str) -> Result<login::Success, io::Error> {
+        self.login(name, None).await
+    }
+
+    /// Logs in as a client, like [`Client::login_offline`], but can also do the key exchange
+    /// with online-mode servers if there's an account to join with.
+    ///
+    /// The Encryption Response of 1.19 to 1.19.2 isn't supported.
+    pub async fn login(&mut self, name: &str, account: Option<&auth::Account>) -> Result<login::Success, io::Error> {
+        let uuid = account.map_or_else(|| login::offline_uuid(name), |a| a.uuid);
+        self.write(&login::Serverbound::login_start(name, uuid, self.version)).await?;
+        loop {
+            match self.read_cb().await? {
+                login::Clientbound::SetCompression(threshold) => self.enable_compression(threshold),
+                login::Clientbound::LoginPluginRequest { message_id, .. } => {
+                    self.write(&login::Serverbound::LoginPluginResponse { message_id, data: None }).await?;
+                }
+                login::Clientbound::EncryptionRequest { server_id, public_key, verify_token, rest } => {
+                    let account = account.ok_or_else(||
+                        io::Error::new(io::ErrorKind::PermissionDenied, "The server is in online mode"))?;
+                    let shared_secret: [u8; 16] = rand::random();
+                    // Since 1.20.5 the server can skip the session server, for offline mode
+                    let authenticate = self.version < protocol::version::SHOULD_AUTHENTICATE || rest.0.first() != Some(&0);
+                    if authenticate {
+                        auth::join(account, &auth::server_hash(&server_id, &shared_secret, &public_key)).await?;
+                    }
+                    self.write(&login::Serverbound::EncryptionResponse {
+                        shared_secret: auth::encrypt(&public_key, &shared_secret)?,
+                        verify_token: auth::encrypt(&public_key, &verify_token)?,
+                    }).await?;
+                    self.enable_encryption(&shared_secret);
+                }
+                login::Clientbound::Disconnect { reason } => return Err(disconnected(&text::plain_or_raw(&reason))),
+                login::Clientbound::LoginSuccess(rest) => {
+                    let success = login::Success::parse(&rest, self.version)?;
+                    if self.version >= protocol::version::LOGIN_ACKNOWLEDGED {
+                        self.write(&login::Serverbound::LoginAcknowledged).await?;
+                    }
+                    return Ok(success);
+                }
+            }
+        }
+    }
+}
+
+impl<S: Transport> Client<Login, S> {
+    /// Moves on to playing after [`Client::login`], going through configuration on versions
+    /// that have it. Known data packs are answered with none, and pings and keep alives are
+    /// answered.
+    pub async fn into_play(self) -> Result<Client<Play, S>, io::Error> {
+        let ids = match configuration::ids(self.version) {
+            Some(ids) => ids,
+            None => return Ok(self.set_protocol(Play)),
+        };
+        let mut client = self.set_protocol(Configuration);
+        loop {
+            let packet = client.read_cb().await?;
+            match packet.id {
+                id if id == ids.finish => {
+                    client.write(&Generic::new(ids.acknowledge_finish, Vec::new())).await?;
+                    return Ok(client.set_protocol(Play));
+                }
+                id if id == ids.disconnect =>
+                    return Err(disconnected(&protocol::disconnect_reason(&packet.data, client.version))),
+                id if id == ids.keep_alive => client.write(&Generic::new(ids.keep_alive_response, packet.data.0)).await?,
+                id if id == ids.ping => client.write(&Generic::new(ids.pong, packet.data.0)).await?,
+                id if Some(id) == ids.known_packs => {
+                    if let Some(response) = ids.known_packs_response {
+                        client.write(&Generic::new(response, vec![0])).await?;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<S: Transport> Client<Play, S> {
+    fn play_ids(&self) -> Result<play::Ids, io::Error> {
+        play::ids(self.version).ok_or_else(||
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Protocol version {} isn't supported", self.version)))
+    }
+    /// Waits for the server to put the player in the world, returning the Join Game packet.
+    pub async fn wait_for_join(&mut self) -> Result<Generic, io::Error> {
+        let ids = self.play_ids()?;
+        loop {
+            let packet = self.read_cb().await?;
+            match packet.id {
+                id if id == ids.join_game => return Ok(packet),
+                id if id == ids.disconnect =>
+                    return Err(disconnected(&protocol::disconnect_reason(&packet.data, self.version))),
+                _ => (),
+            }
+        }
+    }
+    /// Says something in chat.
+    pub async fn chat(&mut self, text: &str) -> Result<(), io::Error> {
+        self.play_ids()?;
+        match play::chat_message(text, self.version) {
+            Some(packet) => self.write(&packet).await,
+            None => Ok(()),
+        }
     }
 }
@@ -0,0 +1,693 @@
+//! Text components, the JSON the game formats text with: status descriptions, disconnect
+//! reasons, chat. Also the legacy `§` codes that came before them, which still turn up in MOTDs,
+//! plugin messages and command output.
+//!
+//! ```
+//! use mcproto_min_async::text::TextComponent;
+//! let motd: TextComponent = serde_json::from_str(
+//!     r#"{"text": "A ", "extra": [{"text": "Minecraft", "color": "gold", "bold": true}, " Server"]}"#,
+//! ).unwrap();
+//! assert_eq!(motd.to_plain(), "A Minecraft Server");
+//! assert_eq!(motd.to_legacy(), "A §6§lMinecraft§r Server");
+//! assert_eq!(motd.to_ansi(), "A \x1b[0;33;1mMinecraft\x1b[0m Server");
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_json::{Map, Value};
+
+/// The character legacy formatting codes start with.
+pub const SECTION: char = '§';
+
+/// A piece of formatted text, and the pieces after it, which inherit its style.
+///
+/// Components can be read as a bare string, a list (the first is the parent of the rest), or an
+/// object; they're always written as an object. They can also be read from NBT, which is how
+/// they're sent since 1.20.3.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextComponent {
+    pub text: String,
+    /// A translation key, filled in with `with`
+    pub translate: Option<String>,
+    pub with: Vec<TextComponent>,
+    /// What to show when the client doesn't know the key, since 1.19.4
+    pub fallback: Option<String>,
+    /// A key binding, shown as whatever key the player has bound it to
+    pub keybind: Option<String>,
+    pub style: Style,
+    /// Put into the chat box when shift-clicked
+    pub insertion: Option<String>,
+    pub click_event: Option<ClickEvent>,
+    pub hover_event: Option<HoverEvent>,
+    pub extra: Vec<TextComponent>,
+}
+
+/// How a component looks. Anything left as `None` is inherited from the parent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub color: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClickEvent {
+    /// `open_url`, `run_command`, `suggest_command`, `change_page` or `copy_to_clipboard`
+    pub action: String,
+    /// Since 1.21.5 this is named for the action instead
+    #[serde(alias = "url", alias = "command", alias = "page", default)]
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HoverEvent {
+    /// `show_text`, `show_item` or `show_entity`
+    pub action: String,
+    /// A component for `show_text`; before 1.16 and since 1.21.5 this is `value`. Since 1.21.5
+    /// items and entities are described in the event itself, which this leaves out.
+    #[serde(alias = "value", default)]
+    pub contents: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+    /// Any color, written as `#RRGGBB`, since 1.16
+    Rgb(u8, u8, u8),
+}
+
+/// A named color, with its legacy code, RGB value and nearest ANSI color.
+struct Named {
+    color: Color,
+    name: &'static str,
+    code: char,
+    rgb: (u8, u8, u8),
+    ansi: u8,
+}
+
+const NAMED: [Named; 16] = [
+    Named { color: Color::Black, name: "black", code: '0', rgb: (0x00, 0x00, 0x00), ansi: 30 },
+    Named { color: Color::DarkBlue, name: "dark_blue", code: '1', rgb: (0x00, 0x00, 0xAA), ansi: 34 },
+    Named { color: Color::DarkGreen, name: "dark_green", code: '2', rgb: (0x00, 0xAA, 0x00), ansi: 32 },
+    Named { color: Color::DarkAqua, name: "dark_aqua", code: '3', rgb: (0x00, 0xAA, 0xAA), ansi: 36 },
+    Named { color: Color::DarkRed, name: "dark_red", code: '4', rgb: (0xAA, 0x00, 0x00), ansi: 31 },
+    Named { color: Color::DarkPurple, name: "dark_purple", code: '5', rgb: (0xAA, 0x00, 0xAA), ansi: 35 },
+    Named { color: Color::Gold, name: "gold", code: '6', rgb: (0xFF, 0xAA, 0x00), ansi: 33 },
+    Named { color: Color::Gray, name: "gray", code: '7', rgb: (0xAA, 0xAA, 0xAA), ansi: 37 },
+    Named { color: Color::DarkGray, name: "dark_gray", code: '8', rgb: (0x55, 0x55, 0x55), ansi: 90 },
+    Named { color: Color::Blue, name: "blue", code: '9', rgb: (0x55, 0x55, 0xFF), ansi: 94 },
+    Named { color: Color::Green, name: "green", code: 'a', rgb: (0x55, 0xFF, 0x55), ansi: 92 },
+    Named { color: Color::Aqua, name: "aqua", code: 'b', rgb: (0x55, 0xFF, 0xFF), ansi: 96 },
+    Named { color: Color::Red, name: "red", code: 'c', rgb: (0xFF, 0x55, 0x55), ansi: 91 },
+    Named { color: Color::LightPurple, name: "light_purple", code: 'd', rgb: (0xFF, 0x55, 0xFF), ansi: 95 },
+    Named { color: Color::Yellow, name: "yellow", code: 'e', rgb: (0xFF, 0xFF, 0x55), ansi: 93 },
+    Named { color: Color::White, name: "white", code: 'f', rgb: (0xFF, 0xFF, 0xFF), ansi: 97 },
+];
+
+impl Color {
+    /// A name like `dark_red`, or `#RRGGBB`.
+    ///
+    /// ```
+    /// use mcproto_min_async::text::Color;
+    /// assert_eq!(Color::from_name("dark_red"), Some(Color::DarkRed));
+    /// assert_eq!(Color::from_name("#3fa9F5"), Some(Color::Rgb(0x3f, 0xa9, 0xf5)));
+    /// assert_eq!(Color::from_name("#+12345"), None);
+    /// assert_eq!(Color::from_name("#12345"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Color> {
+        if let Some(hex) = name.strip_prefix('#') {
+            if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let rgb = u32::from_str_radix(hex, 16).ok()?;
+            return Some(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+        }
+        NAMED.iter().find(|n| n.name == name).map(|n| n.color)
+    }
+
+    pub fn from_legacy(code: char) -> Option<Color> {
+        let code = code.to_ascii_lowercase();
+        NAMED.iter().find(|n| n.code == code).map(|n| n.color)
+    }
+
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            _ => self.named().rgb,
+        }
+    }
+
+    /// The legacy code, going with the closest named color for RGB ones.
+    pub fn legacy_code(self) -> char {
+        self.nearest().code
+    }
+
+    fn named(self) -> &'static Named {
+        NAMED.iter().find(|n| n.color == self).unwrap_or(&NAMED[15])
+    }
+
+    fn nearest(self) -> &'static Named {
+        let (r, g, b) = self.rgb();
+        let dist = |&(nr, ng, nb): &(u8, u8, u8)| {
+            let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+            d(r, nr) + d(g, ng) + d(b, nb)
+        };
+        NAMED.iter().min_by_key(|n| dist(&n.rgb)).unwrap()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Rgb(r, g, b) => write!(f, "#{:02X}{:02X}{:02X}", r, g, b),
+            _ => f.write_str(self.named().name),
+        }
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Color, D::Error> {
+        let name = String::deserialize(de)?;
+        Color::from_name(&name).ok_or_else(|| de::Error::custom(format!("unknown color {:?}", name)))
+    }
+}
+
+impl Style {
+    /// This style, with anything it leaves unset taken from `parent`.
+    pub fn inherit(&self, parent: &Style) -> Style {
+        Style {
+            color: self.color.or(parent.color),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+        }
+    }
+
+    /// The formatting flags that are on, with their legacy code and ANSI SGR parameter.
+    /// Obfuscated text has no ANSI equivalent.
+    fn flags(&self) -> Vec<(char, Option<u8>)> {
+        [
+            (self.obfuscated, 'k', None),
+            (self.bold, 'l', Some(1)),
+            (self.strikethrough, 'm', Some(9)),
+            (self.underlined, 'n', Some(4)),
+            (self.italic, 'o', Some(3)),
+        ].iter().filter(|f| f.0 == Some(true)).map(|f| (f.1, f.2)).collect()
+    }
+
+    fn is_plain(&self) -> bool {
+        self.color.is_none() && self.flags().is_empty()
+    }
+
+    fn legacy(&self) -> String {
+        let mut codes = String::new();
+        if let Some(color) = self.color {
+            codes.push(SECTION);
+            codes.push(color.legacy_code());
+        }
+        for (code, _) in self.flags() {
+            codes.push(SECTION);
+            codes.push(code);
+        }
+        codes
+    }
+
+    fn ansi(&self) -> String {
+        let mut params = vec!["0".to_string()];
+        match self.color {
+            Some(Color::Rgb(r, g, b)) => params.push(format!("38;2;{};{};{}", r, g, b)),
+            Some(color) => params.push(color.named().ansi.to_string()),
+            None => (),
+        }
+        params.extend(self.flags().into_iter().filter_map(|f| f.1).map(|p| p.to_string()));
+        format!("\x1b[{}m", params.join(";"))
+    }
+}
+
+/// The handful of translations that servers send without the client having to know them, like
+/// the ones in disconnect reasons and chat. Anything else is shown as its key and arguments.
+const TRANSLATIONS: &[(&str, &str)] = &[
+    ("chat.type.text", "<%s> %s"),
+    ("chat.type.announcement", "[%s] %s"),
+    ("chat.type.emote", "* %s %s"),
+    ("chat.type.admin", "[%s: %s]"),
+    ("multiplayer.player.joined", "%s joined the game"),
+    ("multiplayer.player.left", "%s left the game"),
+    ("multiplayer.disconnect.kicked", "Kicked by an operator"),
+    ("multiplayer.disconnect.banned", "You are banned from this server"),
+    ("multiplayer.disconnect.banned.reason", "You are banned from this server.\nReason: %s"),
+    ("multiplayer.disconnect.not_whitelisted", "You are not white-listed on this server!"),
+    ("multiplayer.disconnect.server_full", "The server is full!"),
+    ("multiplayer.disconnect.server_shutdown", "Server closed"),
+    ("multiplayer.disconnect.outdated_client", "Incompatible client! Please use %s"),
+    ("multiplayer.disconnect.outdated_server", "Incompatible client! Please use %s"),
+    ("multiplayer.disconnect.duplicate_login", "You logged in from another location"),
+    ("multiplayer.disconnect.idling", "You have been idle for too long!"),
+    ("multiplayer.disconnect.unverified_username", "Failed to verify username!"),
+    ("multiplayer.disconnect.authservers_down", "Authentication servers are down. Please try again later, sorry!"),
+    ("disconnect.timeout", "Timed out"),
+    ("disconnect.closed", "Connection closed"),
+];
+
+/// A run of text in one style, after everything's been flattened out.
+type Span = (Style, String);
+
+impl TextComponent {
+    /// A plain, unstyled component.
+    pub fn text(text: impl Into<String>) -> TextComponent {
+        TextComponent { text: text.into(), ..TextComponent::default() }
+    }
+
+    /// Parses text with legacy `§` codes. A color code resets the formatting, like in game.
+    ///
+    /// ```
+    /// use mcproto_min_async::text::{Color, TextComponent};
+    /// let c = TextComponent::from_legacy("§aGreen §lbold§r plain");
+    /// assert_eq!(c.to_plain(), "Green bold plain");
+    /// assert_eq!(c.extra[1].style.color, Some(Color::Green));
+    /// assert_eq!(c.extra[1].style.bold, Some(true));
+    /// assert_eq!(c.to_legacy(), "§aGreen §a§lbold§r plain");
+    /// ```
+    pub fn from_legacy(legacy: &str) -> TextComponent {
+        let mut root = TextComponent::default();
+        let mut style = Style::default();
+        let mut text = String::new();
+        let mut chars = legacy.chars();
+        while let Some(c) = chars.next() {
+            if c != SECTION {
+                text.push(c);
+                continue;
+            }
+            let code = match chars.next() {
+                Some(code) => code.to_ascii_lowercase(),
+                None => break,
+            };
+            let mut next = style.clone();
+            match code {
+                'k' => next.obfuscated = Some(true),
+                'l' => next.bold = Some(true),
+                'm' => next.strikethrough = Some(true),
+                'n' => next.underlined = Some(true),
+                'o' => next.italic = Some(true),
+                'r' => next = Style::default(),
+                _ => match Color::from_legacy(code) {
+                    Some(color) => next = Style { color: Some(color), ..Style::default() },
+                    // Unknown codes are dropped, like in game
+                    None => continue,
+                },
+            }
+            if !text.is_empty() {
+                root.extra.push(TextComponent { text: std::mem::take(&mut text), style: style.clone(), ..TextComponent::default() });
+            }
+            style = next;
+        }
+        if !text.is_empty() {
+            root.extra.push(TextComponent { text, style, ..TextComponent::default() });
+        }
+        // Don't wrap a single unstyled run
+        if root.extra.len() == 1 && root.extra[0].style.is_plain() {
+            return root.extra.pop().unwrap();
+        }
+        root
+    }
+
+    /// Applies the legacy `§` codes in each component's text, which servers still put in
+    /// descriptions that are otherwise components. The codes only style the rest of the text they're
+    /// in, on top of the component's own style.
+    ///
+    /// ```
+    /// use mcproto_min_async::text::{Color, TextComponent};
+    /// let motd: TextComponent = serde_json::from_str(
+    ///     r##"{"text": "", "extra": [{"text": "Sky", "color": "#3fa9f5"}, {"text": " §lBlock", "color": "gold"}]}"##,
+    /// ).unwrap();
+    /// let expanded = motd.expand_legacy();
+    /// assert_eq!(expanded.to_plain(), "Sky Block");
+    /// assert_eq!(expanded.to_ansi(), "\x1b[0;38;2;63;169;245mSky\x1b[0;33m \x1b[0;33;1mBlock\x1b[0m");
+    /// ```
+    pub fn expand_legacy(&self) -> TextComponent {
+        let mut out = self.clone();
+        out.with = self.with.iter().map(TextComponent::expand_legacy).collect();
+        out.extra = self.extra.iter().map(TextComponent::expand_legacy).collect();
+        if self.text.contains(SECTION) {
+            out.extra.insert(0, TextComponent::from_legacy(&std::mem::take(&mut out.text)));
+        }
+        out
+    }
+
+    /// Flattens the component into runs of styled text.
+    fn spans(&self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        self.push_spans(&Style::default(), &mut spans);
+        spans
+    }
+
+    fn push_spans(&self, parent: &Style, spans: &mut Vec<Span>) {
+        let style = self.style.inherit(parent);
+        let mut push = |text: &str| if !text.is_empty() {
+            spans.push((style.clone(), text.to_string()));
+        };
+        if let Some(key) = &self.translate {
+            match TRANSLATIONS.iter().find(|t| t.0 == key).map(|t| t.1).or(self.fallback.as_deref()) {
+                Some(format) => self.push_translation(format, &style, spans),
+                None => {
+                    push(key);
+                    for (i, arg) in self.with.iter().enumerate() {
+                        spans.push((style.clone(), if i == 0 { " " } else { ", " }.to_string()));
+                        arg.push_spans(&style, spans);
+                    }
+                },
+            }
+        } else if let Some(key) = &self.keybind {
+            push(key);
+        } else {
+            push(&self.text);
+        }
+        for child in &self.extra {
+            child.push_spans(&style, spans);
+        }
+    }
+
+    /// Fills in `%s`, `%1$s` and `%%` in a translation with `with`.
+    fn push_translation(&self, format: &str, style: &Style, spans: &mut Vec<Span>) {
+        let mut next_arg = 0;
+        let mut rest = format;
+        while let Some(i) = rest.find('%') {
+            if i > 0 {
+                spans.push((style.clone(), rest[..i].to_string()));
+            }
+            rest = &rest[i + 1..];
+            let arg = if let Some(after) = rest.strip_prefix('s') {
+                rest = after;
+                next_arg += 1;
+                Some(next_arg - 1)
+            } else if let Some(end) = rest.find("$s").filter(|&end| end > 0 && rest[..end].bytes().all(|b| b.is_ascii_digit())) {
+                let n: usize = rest[..end].parse().unwrap_or(0);
+                rest = &rest[end + 2..];
+                Some(n.saturating_sub(1))
+            } else {
+                // `%%`, or a stray `%`
+                rest = rest.strip_prefix('%').unwrap_or(rest);
+                spans.push((style.clone(), "%".to_string()));
+                None
+            };
+            if let Some(arg) = arg.and_then(|n| self.with.get(n)) {
+                arg.push_spans(style, spans);
+            }
+        }
+        if !rest.is_empty() {
+            spans.push((style.clone(), rest.to_string()));
+        }
+    }
+
+    /// Just the text, without any formatting.
+    pub fn to_plain(&self) -> String {
+        self.spans().into_iter().map(|s| s.1).collect()
+    }
+
+    /// The text with legacy `§` codes. RGB colors become the closest named color.
+    pub fn to_legacy(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        for (style, text) in self.spans() {
+            if style != current {
+                if !current.is_plain() {
+                    // Codes only add formatting, so anything being turned off needs a reset.
+                    // Colors reset too, so don't bother when there's one.
+                    if style.color.is_none() {
+                        out.push(SECTION);
+                        out.push('r');
+                    }
+                }
+                out.push_str(&style.legacy());
+                current = style;
+            }
+            out.push_str(&text);
+        }
+        out
+    }
+
+    /// The text with ANSI escapes, for terminals. Ends with a reset if anything was styled.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        for (style, text) in self.spans() {
+            if style != current {
+                if style.is_plain() {
+                    out.push_str("\x1b[0m");
+                } else {
+                    out.push_str(&style.ansi());
+                }
+                current = style;
+            }
+            out.push_str(&text);
+        }
+        if !current.is_plain() {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+
+    /// Reads any of the forms a component can take.
+    fn from_value(value: Value) -> Result<TextComponent, String> {
+        match value {
+            Value::String(s) => Ok(TextComponent::text(s)),
+            // The game turns these into text too
+            Value::Number(n) => Ok(TextComponent::text(n.to_string())),
+            Value::Bool(b) => Ok(TextComponent::text(b.to_string())),
+            Value::Array(list) => {
+                let mut list = list.into_iter();
+                let mut first = TextComponent::from_value(list.next().ok_or("empty list")?)?;
+                for child in list {
+                    first.extra.push(TextComponent::from_value(child)?);
+                }
+                Ok(first)
+            },
+            Value::Object(obj) => TextComponent::from_object(obj),
+            Value::Null => Err("null".to_string()),
+        }
+    }
+
+    fn from_object(mut obj: Map<String, Value>) -> Result<TextComponent, String> {
+        // NBT lists can only hold one type, so anything in a mixed list that isn't a compound is
+        // wrapped in one, under an empty key
+        if let Some(value) = obj.remove("") {
+            return TextComponent::from_value(value);
+        }
+        fn take<T: de::DeserializeOwned>(obj: &mut Map<String, Value>, key: &str) -> Result<Option<T>, String> {
+            match obj.remove(key) {
+                None | Some(Value::Null) => Ok(None),
+                Some(v) => serde_json::from_value(v).map(Some).map_err(|e| format!("{}: {}", key, e)),
+            }
+        }
+        // NBT doesn't have booleans, so they come as bytes
+        fn take_flag(obj: &mut Map<String, Value>, key: &str) -> Result<Option<bool>, String> {
+            match obj.remove(key) {
+                Some(Value::Number(n)) => Ok(Some(n.as_i64() != Some(0))),
+                Some(v) => { obj.insert(key.to_string(), v); take(obj, key) },
+                None => Ok(None),
+            }
+        }
+        fn take_list(obj: &mut Map<String, Value>, key: &str) -> Result<Vec<TextComponent>, String> {
+            match obj.remove(key) {
+                None | Some(Value::Null) => Ok(Vec::new()),
+                Some(Value::Array(list)) => list.into_iter().map(TextComponent::from_value).collect(),
+                Some(_) => Err(format!("{}: not a list", key)),
+            }
+        }
+        Ok(TextComponent {
+            // Scores and selectors need a server to fill them in, so they're left out
+            text: match obj.remove("text") {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s,
+                Some(v) => v.to_string(),
+            },
+            translate: take(&mut obj, "translate")?,
+            with: take_list(&mut obj, "with")?,
+            fallback: take(&mut obj, "fallback")?,
+            keybind: take(&mut obj, "keybind")?,
+            style: Style {
+                // `reset` and anything this doesn't know leave it to the parent, rather than
+                // losing the whole component
+                color: match obj.remove("color") {
+                    Some(Value::String(name)) => Color::from_name(&name),
+                    _ => None,
+                },
+                bold: take_flag(&mut obj, "bold")?,
+                italic: take_flag(&mut obj, "italic")?,
+                underlined: take_flag(&mut obj, "underlined")?,
+                strikethrough: take_flag(&mut obj, "strikethrough")?,
+                obfuscated: take_flag(&mut obj, "obfuscated")?,
+            },
+            insertion: take(&mut obj, "insertion")?,
+            // Snake case since 1.21.5
+            click_event: match take(&mut obj, "clickEvent")? {
+                Some(event) => Some(event),
+                None => take(&mut obj, "click_event")?,
+            },
+            hover_event: match take(&mut obj, "hoverEvent")? {
+                Some(event) => Some(event),
+                None => take(&mut obj, "hover_event")?,
+            },
+            extra: take_list(&mut obj, "extra")?,
+        })
+    }
+}
+
+/// Translations, with the arguments filled in:
+///
+/// ```
+/// use mcproto_min_async::text::TextComponent;
+/// let chat: TextComponent = serde_json::from_str(
+///     r#"{"translate": "chat.type.text", "with": [{"text": "Steve", "color": "yellow"}, "hi"]}"#,
+/// ).unwrap();
+/// assert_eq!(chat.to_string(), "<Steve> hi");
+/// let unknown: TextComponent = serde_json::from_str(r#"{"translate": "some.key", "with": ["a", 1]}"#).unwrap();
+/// assert_eq!(unknown.to_string(), "some.key a, 1");
+/// ```
+///
+/// Click and hover events in either spelling:
+///
+/// ```
+/// use mcproto_min_async::text::TextComponent;
+/// let old: TextComponent = serde_json::from_str(r#"{"text": "site",
+///     "clickEvent": {"action": "open_url", "value": "https://example.com"},
+///     "hoverEvent": {"action": "show_text", "contents": "Click me"}}"#).unwrap();
+/// let new: TextComponent = serde_json::from_str(r#"{"text": "site",
+///     "click_event": {"action": "open_url", "url": "https://example.com"},
+///     "hover_event": {"action": "show_text", "value": "Click me"}}"#).unwrap();
+/// assert_eq!(old, new);
+/// assert_eq!(new.click_event.unwrap().value, "https://example.com");
+/// assert_eq!(new.hover_event.unwrap().contents, "Click me");
+/// ```
+///
+/// Colors that aren't known are left out:
+///
+/// ```
+/// use mcproto_min_async::text::{Color, TextComponent};
+/// let c: TextComponent = serde_json::from_str(
+///     r#"{"text": "a", "color": "red", "extra": [{"text": "b", "color": "reset"}, {"text": "c", "color": "mauve"}]}"#,
+/// ).unwrap();
+/// assert_eq!(c.style.color, Some(Color::Red));
+/// assert_eq!(c.extra[0].style.color, None);
+/// assert_eq!(c.extra[1].style.color, None);
+/// assert_eq!(c.to_legacy(), "§cabc");
+/// ```
+///
+/// From NBT, where lists that mix strings and compounds wrap the strings:
+///
+/// ```
+/// use mcproto_min_async::nbt::{self, Compression};
+/// use mcproto_min_async::text::TextComponent;
+/// let data = nbt::to_vec("", &serde_json::json!(
+///     {"text": "a", "extra": [{"": "b"}, {"text": "c", "color": "red"}, {"": 1}]}
+/// ), Compression::None).unwrap();
+/// let c: TextComponent = nbt::from_slice(&data).unwrap();
+/// assert_eq!(c.to_legacy(), "ab§cc§r1");
+/// ```
+impl<'de> Deserialize<'de> for TextComponent {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<TextComponent, D::Error> {
+        TextComponent::from_value(Value::deserialize(de)?).map_err(de::Error::custom)
+    }
+}
+
+impl From<&str> for TextComponent {
+    fn from(text: &str) -> TextComponent {
+        TextComponent::text(text)
+    }
+}
+
+impl From<String> for TextComponent {
+    fn from(text: String) -> TextComponent {
+        TextComponent::text(text)
+    }
+}
+
+/// ```
+/// use mcproto_min_async::text::TextComponent;
+/// let c = TextComponent::from_legacy("§cRed");
+/// assert_eq!(serde_json::to_string(&c).unwrap(), r#"{"text":"","extra":[{"text":"Red","color":"red"}]}"#);
+/// let kick: TextComponent = serde_json::from_str(r#"{"translate":"multiplayer.disconnect.kicked"}"#).unwrap();
+/// assert_eq!(serde_json::to_string(&kick).unwrap(), r#"{"translate":"multiplayer.disconnect.kicked"}"#);
+/// ```
+impl Serialize for TextComponent {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = ser.serialize_map(None)?;
+        // The game goes by which key is there, so `text` is only left out for other kinds
+        if let Some(key) = &self.translate {
+            map.serialize_entry("translate", key)?;
+            if !self.with.is_empty() {
+                map.serialize_entry("with", &self.with)?;
+            }
+            if let Some(fallback) = &self.fallback {
+                map.serialize_entry("fallback", fallback)?;
+            }
+        } else if let Some(key) = &self.keybind {
+            map.serialize_entry("keybind", key)?;
+        } else {
+            map.serialize_entry("text", &self.text)?;
+        }
+        let style = &self.style;
+        if let Some(color) = &style.color {
+            map.serialize_entry("color", color)?;
+        }
+        for &(key, flag) in &[("bold", style.bold), ("italic", style.italic), ("underlined", style.underlined),
+                               ("strikethrough", style.strikethrough), ("obfuscated", style.obfuscated)] {
+            if let Some(flag) = flag {
+                map.serialize_entry(key, &flag)?;
+            }
+        }
+        if let Some(insertion) = &self.insertion {
+            map.serialize_entry("insertion", insertion)?;
+        }
+        if let Some(event) = &self.click_event {
+            map.serialize_entry("clickEvent", event)?;
+        }
+        if let Some(event) = &self.hover_event {
+            map.serialize_entry("hoverEvent", event)?;
+        }
+        if !self.extra.is_empty() {
+            map.serialize_entry("extra", &self.extra)?;
+        }
+        map.end()
+    }
+}
+
+/// The plain text of some JSON, or the JSON itself if it isn't a component.
+pub fn plain_or_raw(json: &str) -> String {
+    serde_json::from_str::<TextComponent>(json).map_or_else(|_| json.to_string(), |c| c.to_plain())
+}
+
+/// The plain text.
+impl fmt::Display for TextComponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_plain())
+    }
+}
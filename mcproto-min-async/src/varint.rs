@@ -1,10 +1,79 @@
 //! Minecraft style varints and varlongs.
+//!
+//! Both are little-endian groups of 7 bits, with the top bit set on every byte but the last.
+//! Negative numbers are written as their unsigned two's complement, so they always take the
+//! most bytes. Test vectors from wiki.vg:
+//!
+//! ```
+//! use mcproto_min_async::varint;
+//! let varints: &[(i32, &[u8])] = &[
+//!     (0, &[0x00]),
+//!     (1, &[0x01]),
+//!     (2, &[0x02]),
+//!     (127, &[0x7f]),
+//!     (128, &[0x80, 0x01]),
+//!     (255, &[0xff, 0x01]),
+//!     (25565, &[0xdd, 0xc7, 0x01]),
+//!     (2097151, &[0xff, 0xff, 0x7f]),
+//!     (2147483647, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+//!     (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+//!     (-2147483648, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+//! ];
+//! for &(value, bytes) in varints {
+//!     assert_eq!(varint::encode(value), bytes);
+//!     assert_eq!(varint::read(bytes).unwrap(), value);
+//!     assert_eq!(varint::read_slice(bytes), Some((value, bytes.len())));
+//! }
+//!
+//! let varlongs: &[(i64, &[u8])] = &[
+//!     (0, &[0x00]),
+//!     (1, &[0x01]),
+//!     (2, &[0x02]),
+//!     (127, &[0x7f]),
+//!     (128, &[0x80, 0x01]),
+//!     (255, &[0xff, 0x01]),
+//!     (2147483647, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+//!     (9223372036854775807, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+//!     (-1, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+//!     (-2147483648, &[0x80, 0x80, 0x80, 0x80, 0xf8, 0xff, 0xff, 0xff, 0xff, 0x01]),
+//!     (-9223372036854775808, &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+//! ];
+//! for &(value, bytes) in varlongs {
+//!     assert_eq!(varint::encode_long(value), bytes);
+//!     assert_eq!(varint::read_long(bytes).unwrap(), value);
+//! }
+//! ```
+//!
+//! Anything longer than the most bytes the type can take is rejected, as is a last byte with
+//! more bits than the type has room for. Like the vanilla server, padded encodings that still
+//! fit (like `0x80 0x00` for 0) are accepted.
+//!
+//! ```
+//! use mcproto_min_async::varint;
+//! // Six bytes
+//! assert!(varint::read(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00][..]).is_err());
+//! // Five bytes, but 35 bits
+//! assert!(varint::read(&[0xff, 0xff, 0xff, 0xff, 0x7f][..]).is_err());
+//! // Eleven bytes
+//! assert!(varint::read_long(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00][..]).is_err());
+//! // Ten bytes, but 70 bits
+//! assert!(varint::read_long(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f][..]).is_err());
+//! // Not finished yet
+//! assert_eq!(varint::read_slice(&[0x80, 0x80]), None);
+//! // Padded
+//! assert_eq!(varint::read_slice(&[0x80, 0x00]), Some((0, 2)));
+//! ```
 
 use std::io::{self, Read};
 use serde::{Serialize, Serializer, Deserialize, Deserializer, de};
 use byteorder::{ReadBytesExt};
 //use smallvec::{smallvec, SmallVec};
 
+/// The most bytes a varint can take.
+pub const MAX_VARINT_LEN: usize = 5;
+/// The most bytes a varlong can take.
+pub const MAX_VARLONG_LEN: usize = 10;
+
 /// Helper wrapper to deserialize and serialize as varint automatically.
 #[derive(Debug)]
 pub struct VarInt(pub i32);
@@ -21,6 +90,29 @@ impl Serialize for VarInt {
     }
 }
 
+/// Helper wrapper to deserialize and serialize as varlong automatically.
+///
+/// ```
+/// use mcproto_min_async::{de, ser, varint::VarLong};
+/// let bytes = ser::to_bytes(&VarLong(-2147483648));
+/// assert_eq!(&bytes[..], &[0x80, 0x80, 0x80, 0x80, 0xf8, 0xff, 0xff, 0xff, 0xff, 0x01]);
+/// assert_eq!(de::from_slice::<VarLong>(&bytes).unwrap().0, -2147483648);
+/// ```
+#[derive(Debug)]
+pub struct VarLong(pub i64);
+
+impl<'de> Deserialize<'de> for VarLong {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<VarLong, D::Error> {
+        long::deserialize(de).map(VarLong)
+    }
+}
+
+impl Serialize for VarLong {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_newtype_struct("*VARLONG", &self.0)
+    }
+}
+
 
 pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<i32, D::Error> {
     struct VarIntVisitor;
@@ -39,75 +131,82 @@ pub fn serialize<S: Serializer>(v: &i32, ser: S) -> Result<S::Ok, S::Error> {
     ser.serialize_newtype_struct("*VARINT", v)
 }
 
+/// For `#[serde(with = "varint::long")]`.
+pub mod long {
+    use serde::{Serializer, Deserializer, de};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<i64, D::Error> {
+        struct VarLongVisitor;
+        impl<'de> de::Visitor<'de> for VarLongVisitor {
+            type Value = i64;
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("varlong")
+            }
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(value)
+            }
+        }
+        de.deserialize_newtype_struct("*VARLONG", VarLongVisitor)
+    }
+    pub fn serialize<S: Serializer>(v: &i64, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_newtype_struct("*VARLONG", v)
+    }
+}
+
+/// Reads a varint from the start of a buffer, giving it and how many bytes it took. `None` if
+/// it isn't all there yet, or isn't a valid varint.
 pub fn read_slice(src: &[u8]) -> Option<(i32,usize)> {
     let mut len = 0;
     let read = || { len += 1; src.get(len-1).cloned().ok_or(()) };
-    decode_varint(read, ()).ok().map(|c| (c, len))
+    decode(read, 32, ()).ok().map(|c| (c as u32 as i32, len))
 }
 
 pub fn read(mut src: impl Read) -> io::Result<i32> {
-    decode_varint(|| { src.read_u8() }, io::Error::new(io::ErrorKind::Other, "incorrect varint"))
+    decode(|| { src.read_u8() }, 32, io::Error::new(io::ErrorKind::InvalidData, "incorrect varint"))
+        .map(|v| v as u32 as i32)
 }
 
-fn decode_varint<E>(mut read: impl FnMut() -> Result<u8,E>, err: E) -> Result<i32,E> {
-    let mut radix: u64 = 128;
-    let msb: u8 = 0b10000000; /* Only the MSB set */
-
-    /* First we read the varint as an unsigned int */
-    let mut buf = read()?;
-    let mut res = (buf & (!msb)) as u64;
+pub fn read_long(mut src: impl Read) -> io::Result<i64> {
+    decode(|| { src.read_u8() }, 64, io::Error::new(io::ErrorKind::InvalidData, "incorrect varlong"))
+        .map(|v| v as i64)
+}
 
-    let mut i: usize = 0;
-    while (buf & msb) != 0 {
-        if i >= 5 {
+/// Reads an unsigned number of up to `bits` bits.
+fn decode<E>(mut read: impl FnMut() -> Result<u8,E>, bits: u32, err: E) -> Result<u64,E> {
+    let mut res: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let buf = read()?;
+        let group = (buf & 0x7f) as u64;
+        // Whatever doesn't fit has to be zero. That's also what limits the length.
+        if shift >= bits || (bits - shift < 7 && group >> (bits - shift) != 0) {
             return Err(err);
         }
-
-        i += 1;
-        buf = read()?;
-        res += ((buf & (!msb)) as u64) * radix;
-        radix <<= 7;
-    }
-
-    /* Convert to signed */
-    if res >= 4294967296 {
-        return Err(err);
-    }
-
-    if res > 2147483647 {
-        return Ok((4294967296 - res) as i32 * -1);
-    } else {
-        return Ok(res as i32);
+        res |= group << shift;
+        if buf & 0x80 == 0 {
+            return Ok(res);
+        }
+        shift += 7;
     }
 }
 
-
-pub fn encode(val: i32) -> Vec<u8> {
-    if val == 0 {
-        return vec![0];
-    }
-
-    let mut vec = Vec::new();
-
-    let mut tmp = {
-        if val > 0 {
-            val as u32
-        } else {
-            let mut tmp = (val * -1) as u32;
-            tmp ^= 4294967295;
-            tmp + 1
+fn encode_unsigned(mut val: u64) -> Vec<u8> {
+    let mut vec = Vec::with_capacity(MAX_VARLONG_LEN);
+    loop {
+        let group = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            vec.push(group);
+            return vec;
         }
-    };
-
-    while tmp > 0 {
-        /* By default we add the carrying bit to all bytes */
-        vec.push(((tmp % 128) as u8) | (1 << 7));
-        tmp = tmp >> 7;
+        vec.push(group | 0x80);
     }
+}
 
-    /* and then we remove the carrying bit from the last byte */
-    let i = vec.len() - 1;
-    vec[i] &= !(1 << 7);
+pub fn encode(val: i32) -> Vec<u8> {
+    encode_unsigned(val as u32 as u64)
+}
 
-    vec
+pub fn encode_long(val: i64) -> Vec<u8> {
+    encode_unsigned(val as u64)
 }
@@ -0,0 +1,85 @@
+use std::process::exit;
+use std::time::Duration;
+use std::env;
+use tokio::io::{self, AsyncWriteExt};
+use tokio::time::timeout;
+use mcproto_min_async as mcp;
+use mcp::client;
+use localmc::{find_serverprops, read_properties, server_port, take_root_arg};
+
+
+struct Opts {
+    name: String,
+    say: Option<String>,
+    timeout: Duration,
+}
+
+fn parse_args(args: &[String]) -> Option<Opts> {
+    let mut args = args.iter();
+    let mut opts = Opts { name: "HealthProbe".to_string(), say: None, timeout: Duration::from_secs(30) };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => opts.name = args.next()?.clone(),
+            "--say" => opts.say = Some(args.next()?.clone()),
+            "--timeout" => opts.timeout = Duration::from_secs(args.next()?.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some(opts)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let (root, opts) = match (root, parse_args(&args[1..])) {
+        (Ok(r), Some(o)) => (r, o),
+        _ => {
+            eprintln!("Usage: {} [--root <path>] [--name <player>] [--say <message>] [--timeout <seconds>]", args[0]);
+            exit(1);
+        }
+    };
+
+    let props = match find_serverprops(root.as_deref()) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(10);
+        }
+    };
+    let port = match read_properties(&props).and_then(|p| server_port(&p)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Error reading server.properties: {}", e);
+            exit(20);
+        }
+    };
+
+    match timeout(opts.timeout, probe(port, &opts)).await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => {
+            eprintln!("Probe failed: {}", e);
+            exit(30);
+        }
+        Err(_) => {
+            eprintln!("Probe timed out after {}s", opts.timeout.as_secs());
+            exit(30);
+        }
+    }
+}
+
+async fn probe(port: u16, opts: &Opts) -> io::Result<()> {
+    let addr = format!("127.0.0.1:{}", port);
+    let settings = client::Settings::new().set_host("localhost", port).set_timeout(opts.timeout);
+    // Servers answer status pings from any version, and say which one to log in with
+    let version = client::connect_with_settings(&addr, &settings).await?.status().await?.version.protocol;
+    let mut client = client::connect_login(&addr, &settings.set_version(version)).await?;
+    let profile = client.login_offline(&opts.name).await?;
+    let mut client = client.into_play().await?;
+    client.wait_for_join().await?;
+    println!("Joined as {} ({:032x}) with protocol version {}", profile.name, profile.uuid, version);
+    if let Some(ref message) = opts.say {
+        client.chat(message).await?;
+    }
+    client.write.get_mut().shutdown().await
+}
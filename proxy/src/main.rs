@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::net::SocketAddr;
+use std::process::exit;
+use std::sync::Arc;
+use std::time::Duration;
+use std::env;
+use tokio::io;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+use mcproto_min_async as mcp;
+use mcp::client::{self, Settings, StatusVersion};
+use mcp::protocol::{self, status};
+use mcp::server::{Client, Connection, ConnectionSettings};
+use localmc::{find_serverprops, read_properties, server_port, take_root_arg};
+
+
+struct Opts {
+    listen: String,
+    backend: Option<String>,
+    config: Option<String>,
+    mismatch: String,
+    /// Whether connections come through a load balancer that says who they're from
+    proxy_protocol: bool,
+}
+
+/// Where connections go, from the `--config` file.
+#[derive(Deserialize, Default)]
+struct Routes {
+    /// By the hostname the client connected with
+    #[serde(default, deserialize_with = "normalize_routes")]
+    routes: HashMap<String, Route>,
+    /// For hostnames that aren't in `routes`
+    default: Option<Route>,
+}
+
+#[derive(Deserialize)]
+struct Route {
+    /// Like `survival:25565`
+    backend: String,
+    /// Fields to replace in the backend's status, or to answer with if it's down
+    status: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Forge tacks its marker on after a null, and DNS names can end with a dot or be in any case.
+fn normalize_host(host: &str) -> String {
+    host.split('\0').next().unwrap().trim_end_matches('.').to_lowercase()
+}
+
+/// Keys the routes the way hosts are looked up, so `Play.Example.com.` in the config still matches.
+fn normalize_routes<'de, D: Deserializer<'de>>(de: D) -> Result<HashMap<String, Route>, D::Error> {
+    let mut routes = HashMap::new();
+    for (host, route) in HashMap::<String, Route>::deserialize(de)? {
+        let host = normalize_host(&host);
+        if routes.contains_key(&host) {
+            return Err(serde::de::Error::custom(format!("more than one route for {}", host)));
+        }
+        routes.insert(host, route);
+    }
+    Ok(routes)
+}
+
+impl Routes {
+    fn find(&self, host: &str) -> Option<&Route> {
+        self.routes.get(&normalize_host(host)).or(self.default.as_ref())
+    }
+
+    /// Everything to one backend.
+    fn only(backend: String) -> Self {
+        Routes { default: Some(Route { backend, status: None }), ..Routes::default() }
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Opts> {
+    let mut args = args.iter();
+    let mut opts = Opts {
+        listen: "0.0.0.0:25565".to_string(),
+        backend: None,
+        config: None,
+        proxy_protocol: false,
+        mismatch: "This server is on Minecraft {version}, please switch to it to join".to_string(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => opts.listen = args.next()?.clone(),
+            "--backend" => opts.backend = Some(args.next()?.clone()),
+            "--config" => opts.config = Some(args.next()?.clone()),
+            "--mismatch" => opts.mismatch = args.next()?.clone(),
+            "--proxy-protocol" => opts.proxy_protocol = true,
+            _ => return None,
+        }
+    }
+    if opts.backend.is_some() && opts.config.is_some() {
+        return None;
+    }
+    Some(opts)
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let (root, opts) = match (root, parse_args(&args[1..])) {
+        (Ok(r), Some(o)) => (r, o),
+        _ => {
+            eprintln!("Usage: {} [--root <path>] [--listen <address>] [--backend <address> | --config <path>] [--mismatch <text>] [--proxy-protocol]", args[0]);
+            exit(1);
+        }
+    };
+
+    let routes = match (&opts.config, &opts.backend) {
+        (Some(config), _) => {
+            match File::open(config).map_err(serde_json::Error::io).and_then(serde_json::from_reader) {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("Error reading {}: {}", config, e);
+                    exit(20);
+                }
+            }
+        }
+        (None, Some(backend)) => Routes::only(backend.clone()),
+        (None, None) => {
+            let props = match find_serverprops(root.as_deref()) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(10);
+                }
+            };
+            match read_properties(&props).and_then(|p| server_port(&p)) {
+                Ok(port) => Routes::only(format!("localhost:{}", port)),
+                Err(e) => {
+                    eprintln!("Error reading server.properties: {}", e);
+                    exit(20);
+                }
+            }
+        }
+    };
+
+    if let Err(e) = serve(Arc::new(opts), Arc::new(routes)).await {
+        eprintln!("Error: {}", e);
+        exit(30);
+    }
+}
+
+async fn serve(opts: Arc<Opts>, routes: Arc<Routes>) -> io::Result<()> {
+    let listener = TcpListener::bind(&opts.listen).await?;
+    eprintln!("Proxying {}", opts.listen);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let opts = opts.clone();
+        let routes = routes.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, addr, &opts, &routes).await {
+                eprintln!("{}: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Connecting to the backend the way the client connected to us.
+fn backend_settings<P: protocol::Protocol>(client: &Client<P>) -> Settings {
+    Settings::new().set_version(client.version()).set_host(client.host(), client.port())
+}
+
+/// Asks the backend for its status, the way the client asked.
+async fn backend_status(backend: &str, settings: &Settings) -> io::Result<serde_json::Value> {
+    let data = client::connect_with_settings(backend, settings).await?.status_json().await?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+async fn server_version(backend: &str) -> io::Result<StatusVersion> {
+    // Servers answer status pings from any version
+    Ok(client::connect(backend).await?.status().await?.version)
+}
+
+/// Answers a status ping with the backend's status, with the route's changes.
+async fn answer_status(mut client: Client<protocol::Status>, route: &Route) -> io::Result<()> {
+    let overrides = route.status.as_ref().unwrap();
+    loop {
+        let packet = match client.read().await {
+            Ok(p) => p,
+            // Plenty of clients hang up without pinging
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match packet {
+            status::Serverbound::Request => {
+                let mut data = match backend_status(&route.backend, &backend_settings(&client)).await {
+                    Ok(serde_json::Value::Object(data)) => data,
+                    // Down, which the overrides might be there to say
+                    _ => serde_json::Map::new(),
+                };
+                data.extend(overrides.clone());
+                let data = serde_json::Value::Object(data);
+                client.write(&status::Clientbound::ServerListResp { data: data.to_string() }).await?;
+            }
+            status::Serverbound::Ping(payload) => {
+                client.write(&status::Clientbound::Pong(payload)).await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn handle(stream: TcpStream, addr: SocketAddr, opts: &Opts, routes: &Routes) -> io::Result<()> {
+    let mut settings = ConnectionSettings::new();
+    if opts.proxy_protocol {
+        settings = settings.accept_proxy_protocol();
+    }
+    // Don't let a stuck client hold on to a task forever
+    let conn = timeout(Duration::from_secs(10), Connection::with_settings(stream, &settings)).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "No handshake"))??;
+    // Who it's really from, if the load balancer said
+    let addr = match conn {
+        Connection::Status(ref client) => client.addr(),
+        Connection::Login(ref client) => client.addr(),
+    }.unwrap_or(addr);
+    let (sent, received) = match conn {
+        Connection::Status(client) => {
+            eprintln!("{}: status ping for {}:{} with protocol {}", addr, client.host(), client.port(), client.version());
+            let route = match routes.find(client.host()) {
+                Some(r) => r,
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "No route for that host")),
+            };
+            if route.status.is_some() {
+                return answer_status(client, route).await;
+            }
+            let server = client::connect_with_settings(&route.backend, &backend_settings(&client)).await?.into_inner();
+            mcp::proxy::splice(client, server).await?
+        }
+        Connection::Login(mut client) => {
+            eprintln!("{}: login to {}:{} with protocol {}", addr, client.host(), client.port(), client.version());
+            let route = match routes.find(client.host()) {
+                Some(r) => r,
+                None => {
+                    client.disconnect(&json!({ "text": "There's no server at that address" }).to_string()).await?;
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "No route for that host"));
+                }
+            };
+            let version = match server_version(&route.backend).await {
+                Ok(v) => v,
+                Err(e) => {
+                    client.disconnect(&json!({ "text": "The server isn't available right now" }).to_string()).await?;
+                    return Err(e);
+                }
+            };
+            if version.protocol != client.version() {
+                eprintln!("{}: turned away, {} is on protocol {} ({})", addr, route.backend, version.protocol, version.name);
+                let reason = opts.mismatch.replace("{version}", &version.name);
+                return client.disconnect(&json!({ "text": reason }).to_string()).await;
+            }
+            let server = client::connect_login(&route.backend, &backend_settings(&client)).await?;
+            mcp::proxy::splice(client, server).await?
+        }
+    };
+    eprintln!("{}: closed, {} bytes sent and {} received", addr, sent, received);
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{exit, Command};
+use std::sync::Arc;
+use std::time::Duration;
+use std::env;
+use tokio::io;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use serde_json::json;
+use mcproto_min_async as mcp;
+use mcp::protocol::status;
+use mcp::server::{Connection, Client};
+use localmc::{find_root, read_properties, server_port, take_root_arg};
+
+
+struct Opts {
+    motd: String,
+    online: u32,
+    max: Option<u32>,
+    kick: String,
+    launch: Option<PathBuf>,
+    exit: bool,
+}
+
+fn parse_args(args: &[String]) -> Option<Opts> {
+    let mut args = args.iter();
+    let mut opts = Opts {
+        motd: "Server is sleeping \u{2014} join to wake".to_string(),
+        online: 0,
+        max: None,
+        kick: "Server is starting up, retry in 30s".to_string(),
+        launch: None,
+        exit: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--motd" => opts.motd = args.next()?.clone(),
+            "--online" => opts.online = args.next()?.parse().ok()?,
+            "--max" => opts.max = Some(args.next()?.parse().ok()?),
+            "--kick" => opts.kick = args.next()?.clone(),
+            "--launch" => opts.launch = Some(PathBuf::from(args.next()?)),
+            "--exit" => opts.exit = true,
+            _ => return None,
+        }
+    }
+    Some(opts)
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let (root, mut opts) = match (root, parse_args(&args[1..])) {
+        (Ok(r), Some(o)) => (r, o),
+        _ => {
+            eprintln!("Usage: {} [--root <path>] [--motd <text>] [--online <n>] [--max <n>] [--kick <text>] [--launch <path> | --exit]", args[0]);
+            exit(1);
+        }
+    };
+
+    let root = match find_root(root.as_deref()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(10);
+        }
+    };
+    let props = match read_properties(&root.join("server.properties")) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Error reading server.properties: {}", e);
+            exit(20);
+        }
+    };
+    let port = match server_port(&props) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Error reading server.properties: {}", e);
+            exit(20);
+        }
+    };
+    if opts.max.is_none() {
+        opts.max = Some(max_players(&props));
+    }
+    let launch = opts.launch.take().unwrap_or_else(|| root.join("launch"));
+    let exit_on_wake = opts.exit;
+
+    if let Err(e) = sleep(port, Arc::new(opts)).await {
+        eprintln!("Error listening on port {}: {}", port, e);
+        exit(30);
+    }
+    if exit_on_wake {
+        // Whoever started us starts the server
+        return;
+    }
+    // Someone wants to play, and the port is free again. This process becomes the server.
+    eprintln!("Waking up, running {}", launch.display());
+    let err = Command::new(&launch).current_dir(&root).exec();
+    eprintln!("Error running {}: {}", launch.display(), err);
+    exit(30);
+}
+
+fn max_players(props: &HashMap<String, String>) -> u32 {
+    props.get("max-players").and_then(|m| m.parse().ok()).unwrap_or(20)
+}
+
+/// Answers on the game port until a player tries to log in.
+async fn sleep(port: u16, opts: Arc<Opts>) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    eprintln!("Sleeping on port {}", port);
+    let (wake, mut woken) = mpsc::channel(1);
+    loop {
+        tokio::select! {
+            conn = listener.accept() => {
+                let (stream, addr) = conn?;
+                let opts = opts.clone();
+                let wake = wake.clone();
+                tokio::spawn(async move {
+                    // Don't let a stuck client hold on forever
+                    match timeout(Duration::from_secs(10), answer(stream, &opts)).await {
+                        Ok(Ok(Some(name))) => {
+                            eprintln!("{} ({}) wants to play", name, addr);
+                            let _ = wake.send(()).await;
+                        }
+                        Ok(Ok(None)) => (),
+                        Ok(Err(e)) => eprintln!("Error talking to {}: {}", addr, e),
+                        Err(_) => eprintln!("{} timed out", addr),
+                    }
+                });
+            }
+            _ = woken.recv() => return Ok(()),
+        }
+    }
+}
+
+/// Handles one connection, giving the player's name if they tried to log in.
+async fn answer(stream: tokio::net::TcpStream, opts: &Opts) -> io::Result<Option<String>> {
+    match Connection::new(stream).await? {
+        Connection::Status(client) => {
+            answer_status(client, opts).await?;
+            Ok(None)
+        }
+        Connection::Login(mut client) => {
+            let (name, _) = client.read_login_start().await?;
+            client.disconnect(&json!({ "text": opts.kick }).to_string()).await?;
+            Ok(Some(name))
+        }
+    }
+}
+
+async fn answer_status(mut client: Client<mcp::protocol::Status>, opts: &Opts) -> io::Result<()> {
+    loop {
+        let packet = match client.read().await {
+            Ok(p) => p,
+            // Plenty of clients hang up without pinging
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match packet {
+            status::Serverbound::Request => {
+                let data = json!({
+                    // Whatever the client speaks, so it isn't shown as incompatible
+                    "version": { "name": "Sleeping", "protocol": client.version() },
+                    "players": { "online": opts.online, "max": opts.max },
+                    "description": { "text": opts.motd },
+                });
+                client.write(&status::Clientbound::ServerListResp { data: data.to_string() }).await?;
+            }
+            status::Serverbound::Ping(payload) => {
+                client.write(&status::Clientbound::Pong(payload)).await?;
+                return Ok(());
+            }
+        }
+    }
+}
@@ -1,11 +1,12 @@
 #![recursion_limit="256"]
-use tokio::prelude::*;
+use tokio::io;
 
-use tokio::net::TcpStream;
 use mcproto_min_async as mcp;
+use mcp::client::{self, StatusResponse};
+use std::io::IsTerminal;
 use std::path::Path;
 use failure::Error;
-use localmc::{find_serverprops, read_properties};
+use localmc::{find_serverprops, read_properties, server_paused, take_root_arg};
 
 
 fn get_server_port(path: &Path) -> Result<u16, Error> {
@@ -17,14 +18,56 @@ fn get_server_port(path: &Path) -> Result<u16, Error> {
     }
 }
 
-#[tokio::main]
+/// The description out of a status response, in color if it's going to a terminal.
+fn render_motd(status: &StatusResponse) -> String {
+    // Servers still put legacy codes in descriptions, even ones that are otherwise components
+    let motd = status.description.expand_legacy();
+    if std::io::stdout().is_terminal() { motd.to_ansi() } else { motd.to_plain() }
+}
+
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> io::Result<()> {
-    let port = match get_server_port(&match find_serverprops() {
-        Some(p) => p,
-        None => {
-            return Err(io::Error::new(io::ErrorKind::Other, "Unable to find server.properties"));
+    let mut args: Vec<String> = std::env::args().collect();
+    let root = take_root_arg(&mut args);
+    let mut proxy_version = None;
+    let mut motd = false;
+    let mut favicon = None;
+    let mut ok = root.is_ok();
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            // For when the server expects connections through a load balancer
+            "--proxy-protocol" => match rest.next().map(String::as_str) {
+                Some("v1") => proxy_version = Some(1),
+                Some("v2") => proxy_version = Some(2),
+                _ => ok = false,
+            },
+            "--motd" => motd = true,
+            "--favicon" => match rest.next() {
+                Some(path) => favicon = Some(path.clone()),
+                None => ok = false,
+            },
+            _ => ok = false,
+        }
+    }
+    let root = match root {
+        Ok(r) if ok => r,
+        _ => {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("Usage: {} [--root <path>] [--proxy-protocol v1|v2] [--motd] [--favicon <out.png>]", args[0])));
+        }
+    };
+    let props = match find_serverprops(root.as_deref()) {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("{}", e)));
         }
-    }) {
+    };
+    // Pinging a paused server wakes it, which the healthcheck shouldn't do every 30 seconds
+    if !motd && favicon.is_none() && props.parent().is_some_and(server_paused) {
+        eprintln!("The server is paused until someone connects");
+        return Ok(());
+    }
+    let port = match get_server_port(&props) {
         Ok(p) => p,
         Err(e) => {
             return Err(io::Error::new(io::ErrorKind::Other, format!("{}", e)))
@@ -32,23 +75,25 @@ async fn main() -> io::Result<()> {
     };
 
 
-    let upstream = TcpStream::connect(format!("127.0.0.1:{}", port)).await?;
-    let mut upstream = mcp::server::Client::create(upstream, mcp::protocol::Handshake);
-    let p = mcp::protocol::handshake::Serverbound::ServerListPing {
-        version: 498,
-        host: "localhost".to_string(),
-        port: 25567,
-        next_state: mcp::protocol::ProtocolState::Status
-    };
-    upstream.write(&p).await?;
-    let mut upstream = upstream.set_protocol(mcp::protocol::Status);
-    upstream.write(&mcp::protocol::status::Serverbound::Request).await?;
-    match upstream.read_cb().await? {
-        mcp::protocol::status::Clientbound::ServerListResp { data } => 
-            println!("{}", data),
-        c => 
-            panic!("Received an unexpected packet {:?}", c)
+    let mut settings = client::Settings::new().set_host("localhost", port);
+    if let Some(version) = proxy_version {
+        settings = settings.send_proxy_protocol(version);
+    }
+    let mut upstream = client::connect_with_settings(&format!("127.0.0.1:{}", port), &settings).await?;
+    let data = upstream.status_json().await?;
+    if favicon.is_some() || motd {
+        let status: StatusResponse = serde_json::from_str(&data)?;
+        if let Some(path) = favicon {
+            let png = status.favicon_png()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "The server has no icon"))??;
+            std::fs::write(path, png)?;
+        }
+        if motd {
+            println!("{}", render_motd(&status));
+            return Ok(());
+        }
     }
+    println!("{}", data);
 
     Ok(())
 }
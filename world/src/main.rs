@@ -0,0 +1,503 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::env;
+use failure::Fallible;
+use serde::{Deserialize, Serialize};
+use localmc::{read_properties, find_root, take_root_arg, server_running};
+use localmc::world::{read_level, world_dir, dimension_dir, game_type_name, session_locked, Level};
+use localmc::region::{self, Region, ChunkInfo};
+use chrono::{DateTime, Utc};
+use localmc::player::{self, PlayerData, Stats, Advancements};
+use localmc::profiles::resolve_uuid;
+use uuid::Uuid;
+
+
+enum Command {
+    Info,
+    Player(String),
+    Regions,
+    Prune,
+}
+
+struct Opts {
+    world: Option<PathBuf>,
+    json: bool,
+    verbose: bool,
+    dimension: String,
+    min_inhabited: Option<i64>,
+    radius: Option<i64>,
+    dry_run: bool,
+    command: Command,
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let root = take_root_arg(&mut args);
+    let (root, opts) = match (root, parse_args(&args[1..])) {
+        (Ok(r), Some(o)) => (r, o),
+        _ => {
+            eprintln!("Usage: {} [--root <path>] [--world <path>] [info] [--json]", args[0]);
+            eprintln!("       {} [--root <path>] [--world <path>] player <name|uuid> [--json]", args[0]);
+            eprintln!("       {} [--root <path>] [--world <path>] regions [--dimension <name>] [--verbose] [--json]", args[0]);
+            eprintln!("       {} [--root <path>] [--world <path>] prune [--min-inhabited <ticks>] [--radius <blocks>] [--dimension <name>] [--dry-run]", args[0]);
+            exit(1);
+        }
+    };
+
+    let world = match opts.world {
+        Some(ref w) => w.clone(),
+        None => match find_world(root.as_deref()) {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(10);
+            }
+        }
+    };
+
+    let result = match opts.command {
+        Command::Info => info(&world, opts.json),
+        Command::Player(ref who) => show_player(root.as_deref(), &world, who, opts.json),
+        Command::Regions => regions(&world, &opts),
+        Command::Prune => prune(root.as_deref(), &world, &opts),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(20);
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Opts> {
+    let mut args = args.iter();
+    let mut world = None;
+    let mut json = false;
+    let mut verbose = false;
+    let mut dimension = "overworld".to_string();
+    let mut min_inhabited = None;
+    let mut radius = None;
+    let mut dry_run = false;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--world" => world = Some(PathBuf::from(args.next()?)),
+            "--json" => json = true,
+            "--verbose" | "-v" => verbose = true,
+            "--dimension" => dimension = args.next()?.clone(),
+            "--min-inhabited" => min_inhabited = Some(args.next()?.parse().ok()?),
+            "--radius" => radius = Some(args.next()?.parse().ok()?),
+            "--dry-run" => dry_run = true,
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let command = match positional[..] {
+        [] | ["info"] => Command::Info,
+        ["player", who] => Command::Player(who.to_string()),
+        ["regions"] => Command::Regions,
+        // Pruning needs something to go by
+        ["prune"] if min_inhabited.is_some() || radius.is_some() => Command::Prune,
+        _ => return None,
+    };
+    Some(Opts { world, json, verbose, dimension, min_inhabited, radius, dry_run, command })
+}
+
+/// The world directory of the server, when one isn't given.
+fn find_world(root: Option<&Path>) -> Fallible<PathBuf> {
+    let root = find_root(root)?;
+    let props = read_properties(&root.join("server.properties"))?;
+    Ok(world_dir(&root, &props))
+}
+
+#[derive(Serialize)]
+struct Info<'a> {
+    level_name: &'a str,
+    seed: Option<i64>,
+    game_type: &'static str,
+    difficulty: &'static str,
+    hardcore: bool,
+    spawn: Option<[i32; 3]>,
+    time: i64,
+    day_time: Option<i64>,
+    data_version: Option<i32>,
+    version: Option<&'a str>,
+    enabled_data_packs: &'a [String],
+    disabled_data_packs: &'a [String],
+    game_rules: &'a BTreeMap<String, String>,
+}
+
+impl<'a> From<&'a Level> for Info<'a> {
+    fn from(level: &'a Level) -> Self {
+        Info {
+            level_name: &level.level_name,
+            seed: level.seed(),
+            game_type: level.game_type_name(),
+            difficulty: level.difficulty_name(),
+            hardcore: level.hardcore,
+            spawn: level.spawn_pos(),
+            time: level.time,
+            day_time: level.day_time,
+            data_version: level.data_version,
+            version: level.version.as_ref().map(|v| v.name.as_str()),
+            enabled_data_packs: level.data_packs.as_ref().map(|p| &p.enabled[..]).unwrap_or(&[]),
+            disabled_data_packs: level.data_packs.as_ref().map(|p| &p.disabled[..]).unwrap_or(&[]),
+            game_rules: &level.game_rules,
+        }
+    }
+}
+
+fn info(world: &Path, json: bool) -> Fallible<()> {
+    let level = read_level(world)?;
+    let info = Info::from(&level);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+    let or_unknown = |v: Option<String>| v.unwrap_or_else(|| "unknown".to_string());
+    println!("Level name:   {}", info.level_name);
+    println!("Seed:         {}", or_unknown(info.seed.map(|s| s.to_string())));
+    println!("Game type:    {}{}", info.game_type, if info.hardcore { " (hardcore)" } else { "" });
+    println!("Difficulty:   {}", info.difficulty);
+    println!("Spawn:        {}", or_unknown(info.spawn.map(|[x, y, z]| format!("{} {} {}", x, y, z))));
+    println!("Time:         {} (day time {})", info.time, or_unknown(info.day_time.map(|t| t.to_string())));
+    println!("Data version: {} ({})", or_unknown(info.data_version.map(|v| v.to_string())), info.version.unwrap_or("unknown"));
+    println!("Data packs:   {}", info.enabled_data_packs.join(", "));
+    if !info.disabled_data_packs.is_empty() {
+        println!("Disabled:     {}", info.disabled_data_packs.join(", "));
+    }
+    println!("Game rules:");
+    for (rule, value) in info.game_rules {
+        println!("  {} = {}", rule, value);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct PlayerReport {
+    uuid: Uuid,
+    data: Option<PlayerData>,
+    stats: Option<Stats>,
+    advancements: Option<Advancements>,
+}
+
+fn show_player(root: Option<&Path>, world: &Path, who: &str, json: bool) -> Fallible<()> {
+    let uuid = match who.parse() {
+        Ok(uuid) => uuid,
+        // Looking up a name needs the server's settings and usercache.json
+        Err(_) => {
+            let root = find_root(root)?;
+            resolve_uuid(&root, &read_properties(&root.join("server.properties"))?, who)?
+        }
+    };
+    let report = PlayerReport {
+        uuid,
+        data: player::read_player(world, &uuid)?,
+        stats: player::read_stats(world, &uuid)?,
+        advancements: player::read_advancements(world, &uuid)?,
+    };
+    if report.data.is_none() && report.stats.is_none() && report.advancements.is_none() {
+        failure::bail!("{} ({}) has never played in {}", who, uuid, world.display());
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Player:       {} ({})", who, uuid);
+    if let Some(ref data) = report.data {
+        let pos = &data.pos;
+        if pos.len() == 3 {
+            println!("Position:     {:.1} {:.1} {:.1} in {}", pos[0], pos[1], pos[2], data.dimension_name());
+        }
+        println!("Game type:    {}", game_type_name(data.game_type));
+        println!("Health:       {}", data.health);
+        println!("Food:         {}", data.food_level);
+        println!("XP:           level {} ({} total)", data.xp_level, data.xp_total);
+    }
+    if let Some(ticks) = report.stats.as_ref().and_then(|s| s.play_time) {
+        let minutes = ticks / 20 / 60;
+        println!("Playtime:     {}h {}m", minutes / 60, minutes % 60);
+    }
+    if let Some(ref adv) = report.advancements {
+        println!("Advancements: {} done, {} in progress", adv.done.len(), adv.in_progress.len());
+    }
+    if let Some(ref data) = report.data {
+        println!("Inventory:    {} stacks", data.inventory.len());
+        for (id, count) in player::item_totals(&data.inventory) {
+            println!("  {:>5} {}", count, id);
+        }
+        println!("Ender chest:  {} stacks", data.ender_items.len());
+        for (id, count) in player::item_totals(&data.ender_items) {
+            println!("  {:>5} {}", count, id);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct RegionReport {
+    file: String,
+    x: i32,
+    z: i32,
+    file_size: u64,
+    chunks: usize,
+    external_chunks: usize,
+    /// Compressed chunk data, not counting padding
+    data_bytes: u64,
+    oldest: Option<u32>,
+    newest: Option<u32>,
+    problems: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chunk_details: Vec<ChunkInfo>,
+}
+
+fn region_report(path: &Path, verbose: bool) -> RegionReport {
+    let (x, z) = region::region_coords(path).unwrap_or((0, 0));
+    let mut report = RegionReport {
+        file: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        x, z,
+        file_size: 0, chunks: 0, external_chunks: 0, data_bytes: 0,
+        oldest: None, newest: None, problems: 0,
+        error: None, chunk_details: Vec::new(),
+    };
+    let result = Region::open(path).and_then(|mut r| {
+        report.file_size = r.file_len();
+        r.chunks()
+    });
+    let chunks = match result {
+        Ok(c) => c,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            report.problems = 1;
+            return report;
+        }
+    };
+    report.chunks = chunks.len();
+    for chunk in &chunks {
+        report.data_bytes += chunk.length as u64;
+        if chunk.external {
+            report.external_chunks += 1;
+        }
+        if !chunk.problems.is_empty() {
+            report.problems += 1;
+        }
+        if chunk.timestamp != 0 {
+            report.oldest = Some(report.oldest.map_or(chunk.timestamp, |t| t.min(chunk.timestamp)));
+            report.newest = Some(report.newest.map_or(chunk.timestamp, |t| t.max(chunk.timestamp)));
+        }
+    }
+    // Problem chunks are always shown
+    report.chunk_details = chunks.into_iter().filter(|c| verbose || !c.problems.is_empty()).collect();
+    report
+}
+
+fn format_time(ts: Option<u32>) -> String {
+    match ts.and_then(|ts| DateTime::<Utc>::from_timestamp(ts as i64, 0)) {
+        Some(t) => t.format("%Y-%m-%d %H:%M").to_string(),
+        None => "never".to_string(),
+    }
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0
+}
+
+fn regions(world: &Path, opts: &Opts) -> Fallible<()> {
+    let dir = dimension_dir(world, &opts.dimension).join("region");
+    let reports: Vec<RegionReport> = region::region_files(&dir)?.iter()
+        .map(|path| region_report(path, opts.verbose))
+        .collect();
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    for r in &reports {
+        if let Some(ref e) = r.error {
+            println!("{:<16} {}", r.file, e);
+            continue;
+        }
+        println!(
+            "{:<16} {:>4} chunks  {:>8.2} MiB on disk  {:>8.2} MiB data  {} .. {}{}",
+            r.file, r.chunks, mib(r.file_size), mib(r.data_bytes),
+            format_time(r.oldest), format_time(r.newest),
+            if r.external_chunks > 0 { format!("  {} external", r.external_chunks) } else { String::new() },
+        );
+        for c in &r.chunk_details {
+            if c.problems.is_empty() {
+                println!(
+                    "  chunk {:>5} {:>5}  {:>3} sectors  {:>8} bytes  {:?}{}  {}",
+                    c.x, c.z, c.location.sectors, c.length, c.compression,
+                    if c.external { " (external)" } else { "" }, format_time(Some(c.timestamp)),
+                );
+            }
+            for p in &c.problems {
+                println!("  chunk {:>5} {:>5}  {}", c.x, c.z, p);
+            }
+        }
+    }
+    println!(
+        "Total: {} regions, {} chunks, {:.2} MiB on disk, {:.2} MiB data, {} with problems",
+        reports.len(),
+        reports.iter().map(|r| r.chunks).sum::<usize>(),
+        mib(reports.iter().map(|r| r.file_size).sum()),
+        mib(reports.iter().map(|r| r.data_bytes).sum()),
+        reports.iter().map(|r| r.problems).sum::<usize>(),
+    );
+    Ok(())
+}
+
+/// The parts of a chunk pruning looks at. 1.18 moved everything out of `Level`.
+#[derive(Deserialize)]
+struct ChunkTimes {
+    #[serde(rename = "InhabitedTime")]
+    inhabited_time: Option<i64>,
+    #[serde(rename = "Level")]
+    level: Option<Box<ChunkTimes>>,
+}
+
+impl ChunkTimes {
+    fn inhabited_time(&self) -> Option<i64> {
+        self.inhabited_time.or_else(|| self.level.as_ref()?.inhabited_time())
+    }
+}
+
+/// Refuses to touch a world a server has open.
+fn ensure_stopped(root: Option<&Path>, world: &Path) -> Fallible<()> {
+    if session_locked(world)? {
+        failure::bail!("A server has {} open, stop it first", world.display());
+    }
+    // Without a root there's no port to check, but the lock covers that
+    if let Ok(root) = find_root(root) {
+        if server_running(&read_properties(&root.join("server.properties"))?)? {
+            failure::bail!("The server is running, stop it first");
+        }
+    }
+    Ok(())
+}
+
+/// Which chunks of a region to delete. Chunks that can't be read are kept.
+fn doomed_chunks(region: &mut Region, spawn: (i64, i64), opts: &Opts) -> Fallible<BTreeSet<usize>> {
+    let mut doomed = BTreeSet::new();
+    for chunk in region.chunks()? {
+        let (x, z) = ((chunk.x as i64) * 16 + 8, (chunk.z as i64) * 16 + 8);
+        if let Some(radius) = opts.radius {
+            let (dx, dz) = (x - spawn.0, z - spawn.1);
+            if dx * dx + dz * dz > radius * radius {
+                doomed.insert(chunk.index);
+                continue;
+            }
+        }
+        if let Some(min) = opts.min_inhabited {
+            match region.read_chunk::<ChunkTimes>(chunk.index) {
+                Ok(Some(times)) => if times.inhabited_time().unwrap_or(0) < min {
+                    doomed.insert(chunk.index);
+                },
+                Ok(None) => (),
+                Err(e) => eprintln!("Keeping chunk {} {}, it can't be read: {}", chunk.x, chunk.z, e),
+            }
+        }
+    }
+    Ok(doomed)
+}
+
+/// Deletes chunks from a region file, or works out what that would save. Returns the bytes saved.
+/// Opens the region file of each kind that has one, as long as none of them have broken chunks.
+fn open_all(dir: &Path, name: &str) -> Result<Vec<Region>, String> {
+    let mut regions = Vec::new();
+    // Entities (since 1.17) and points of interest are kept per chunk in matching files
+    for kind in &["region", "entities", "poi"] {
+        let path = dir.join(kind).join(name);
+        if !path.exists() {
+            continue;
+        }
+        let mut region = Region::open(&path).map_err(|e| e.to_string())?;
+        let chunks = region.chunks().map_err(|e| e.to_string())?;
+        if chunks.iter().any(|c| !c.problems.is_empty()) {
+            return Err(format!("{}/{} has broken chunks, see `regions`", kind, name));
+        }
+        regions.push(region);
+    }
+    Ok(regions)
+}
+
+/// Removes the chunks from every file, or none of them if any can't be rewritten. Returns the
+/// bytes saved.
+fn prune_all(regions: Vec<Region>, doomed: &BTreeSet<usize>, dry_run: bool) -> Fallible<u64> {
+    let before: u64 = regions.iter().map(Region::file_len).sum();
+    let keep = |i| !doomed.contains(&i);
+    let after = if dry_run {
+        let mut after = 0;
+        for mut region in regions {
+            after += region.packed_len(keep)?;
+        }
+        after
+    } else {
+        let staged = regions.into_iter().map(|r| r.stage_rewrite(keep)).collect::<Fallible<Vec<_>>>()?;
+        let mut after = 0;
+        for rewrite in staged {
+            after += rewrite.commit()?;
+        }
+        after
+    };
+    Ok(before.saturating_sub(after))
+}
+
+fn prune(root: Option<&Path>, world: &Path, opts: &Opts) -> Fallible<()> {
+    ensure_stopped(root, world)?;
+    let level = read_level(world)?;
+    let [x, _, z] = match level.spawn_pos() {
+        Some(pos) => pos,
+        None => failure::bail!("level.dat doesn't say where spawn is"),
+    };
+    let mut spawn = (x as i64, z as i64);
+    if matches!(opts.dimension.strip_prefix("minecraft:").unwrap_or(&opts.dimension), "the_nether" | "nether") {
+        spawn = (spawn.0 / 8, spawn.1 / 8);
+    }
+    let dir = dimension_dir(world, &opts.dimension);
+    let verb = if opts.dry_run { "would remove" } else { "removed" };
+    let (mut total_chunks, mut total_removed, mut total_bytes) = (0, 0, 0);
+    for path in region::region_files(&dir.join("region"))? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let mut region = match Region::open(&path) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("{:<16} skipped: {}", name, e);
+                continue;
+            }
+        };
+        let chunks = region.chunks()?;
+        if chunks.iter().any(|c| !c.problems.is_empty()) {
+            println!("{:<16} skipped: it has broken chunks, see `regions`", name);
+            continue;
+        }
+        let doomed = doomed_chunks(&mut region, spawn, opts)?;
+        drop(region);
+        total_chunks += chunks.len();
+        if doomed.is_empty() {
+            continue;
+        }
+
+        // Check every file first, so they're all pruned or none of them are
+        let regions = match open_all(&dir, &name) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("{:<16} skipped: {}", name, e);
+                continue;
+            }
+        };
+        let bytes = prune_all(regions, &doomed, opts.dry_run)?;
+        println!(
+            "{:<16} {} {:>4} of {:>4} chunks, {:.2} MiB",
+            name, verb, doomed.len(), chunks.len(), mib(bytes),
+        );
+        total_removed += doomed.len();
+        total_bytes += bytes;
+    }
+    println!(
+        "Total: {} {} of {} chunks, {:.2} MiB",
+        verb, total_removed, total_chunks, mib(total_bytes),
+    );
+    Ok(())
+}